@@ -4,12 +4,11 @@ use smithay_client_toolkit::shell::wlr_layer::{Anchor, KeyboardInteractivity};
 fn main() -> layer_shell_wgpu_egui::Result {
     env_logger::init();
 
-    let options = LayerShellOptions {
-        height: 100,
-        anchor: Some(Anchor::TOP | Anchor::LEFT | Anchor::RIGHT),
-        keyboard_interactivity: Some(KeyboardInteractivity::OnDemand),
-        ..Default::default()
-    };
+    let options = LayerShellOptions::builder()
+        .size(0, 100)
+        .anchor(Anchor::TOP | Anchor::LEFT | Anchor::RIGHT)
+        .keyboard_interactivity(KeyboardInteractivity::OnDemand)
+        .build()?;
 
     layer_shell_wgpu_egui::run_layer(
         options,
@@ -38,6 +37,11 @@ impl layer_shell_wgpu_egui::App for MyApp {
             if ui.button("click me").clicked() {
                 println!("Button clicked!");
             }
+            if ui.button("quit").clicked() {
+                if let Some(handle) = layer_shell_wgpu_egui::LayerShellHandle::from_context(ctx) {
+                    handle.exit();
+                }
+            }
             ui.label(format!("Hello '{}', age {}", self.name, self.age));
         });
     }
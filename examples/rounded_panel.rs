@@ -0,0 +1,29 @@
+use layer_shell_wgpu_egui::layer_shell::LayerShellOptions;
+use smithay_client_toolkit::shell::wlr_layer::{Anchor, Layer};
+
+fn main() -> layer_shell_wgpu_egui::Result {
+    env_logger::init();
+
+    // the buffer is cleared to transparent, so everything outside the rounded frame shows
+    // what is below the surface
+    let options = LayerShellOptions {
+        layer: Some(Layer::Top),
+        anchor: Some(Anchor::TOP),
+        width: 400,
+        height: 120,
+        margin: Some((8, 0, 0, 0)),
+        ..Default::default()
+    };
+
+    layer_shell_wgpu_egui::run_layer_simple(options, move |ctx| {
+        let frame = egui::Frame::new()
+            .fill(egui::Color32::from_rgba_unmultiplied(30, 30, 30, 230))
+            .corner_radius(16)
+            .inner_margin(16);
+
+        egui::CentralPanel::default().frame(frame).show(ctx, |ui| {
+            ui.heading("Rounded panel");
+            ui.label("The corners are drawn by egui, the compositor blends them.");
+        });
+    })
+}
@@ -0,0 +1,72 @@
+use std::sync::{Arc, Mutex};
+
+use accesskit_unix::{ActionHandler, ActivationHandler, Adapter, DeactivationHandler};
+use egui::accesskit::{ActionRequest, TreeUpdate};
+
+/// Exposes the widgets of one surface to assistive technology over AT-SPI.
+///
+/// The adapter calls its handlers from its own thread, action requests are queued and
+/// passed to egui with the next frame.
+pub(crate) struct Accessibility {
+    adapter: Adapter,
+    action_requests: Arc<Mutex<Vec<ActionRequest>>>,
+}
+
+impl Accessibility {
+    pub(crate) fn new(context: &egui::Context) -> Self {
+        let action_requests = Arc::default();
+        let adapter = Adapter::new(
+            Activation(context.clone()),
+            Actions {
+                context: context.clone(),
+                action_requests: Arc::clone(&action_requests),
+            },
+            Deactivation,
+        );
+        Self {
+            adapter,
+            action_requests,
+        }
+    }
+
+    pub(crate) fn take_action_requests(&self) -> Vec<ActionRequest> {
+        std::mem::take(&mut *self.action_requests.lock().unwrap())
+    }
+
+    pub(crate) fn set_focused(&mut self, focused: bool) {
+        self.adapter.update_window_focus_state(focused);
+    }
+
+    pub(crate) fn update(&mut self, update: TreeUpdate) {
+        self.adapter.update_if_active(|| update);
+    }
+}
+
+struct Activation(egui::Context);
+
+impl ActivationHandler for Activation {
+    fn request_initial_tree(&mut self) -> Option<TreeUpdate> {
+        // egui only builds the tree once enabled, it is sent with the next frame
+        self.0.enable_accesskit();
+        self.0.request_repaint();
+        None
+    }
+}
+
+struct Actions {
+    context: egui::Context,
+    action_requests: Arc<Mutex<Vec<ActionRequest>>>,
+}
+
+impl ActionHandler for Actions {
+    fn do_action(&mut self, request: ActionRequest) {
+        self.action_requests.lock().unwrap().push(request);
+        self.context.request_repaint();
+    }
+}
+
+struct Deactivation;
+
+impl DeactivationHandler for Deactivation {
+    fn deactivate_accessibility(&mut self) {}
+}
@@ -1,10 +1,15 @@
-use std::cell::RefCell;
+use std::{
+    cell::RefCell,
+    os::fd::{AsFd, BorrowedFd},
+    time::{Duration, Instant},
+};
 
 use smithay_client_toolkit::reexports::calloop::EventLoop;
+use wayland_client::protocol::wl_output::WlOutput;
 
 use crate::{
-    layer_shell::{LayerShellOptions, WgpuLayerShellState},
-    App, AppCreator, Result,
+    layer_shell::{LayerShellError, LayerShellOptions, SurfaceId, WgpuLayerShellState},
+    App, AppCreator, Image, Result,
 };
 
 pub struct WgpuLayerShellApp {
@@ -14,38 +19,201 @@ pub struct WgpuLayerShellApp {
 }
 
 impl WgpuLayerShellApp {
-    pub fn new(layer_shell_options: LayerShellOptions, app_creator: AppCreator) -> Self {
-        let event_loop = EventLoop::try_new().expect("Could not create event loop.");
-        let layer_shell_state = WgpuLayerShellState::new(event_loop.handle(), layer_shell_options);
-
-        Self {
-            // TODO: find better way to handle this potential error
-            application: RefCell::new(
-                app_creator(&layer_shell_state.egui_state.context()).expect("could not create app"),
-            ),
+    pub fn new(layer_shell_options: LayerShellOptions, app_creator: AppCreator) -> Result<Self> {
+        let event_loop = EventLoop::try_new().map_err(LayerShellError::from)?;
+        let layer_shell_state = WgpuLayerShellState::new(event_loop.handle(), layer_shell_options)?;
+
+        let application = app_creator(layer_shell_state.surfaces[0].context())?;
+
+        Ok(Self {
+            application: RefCell::new(application),
             event_loop,
             layer_shell_state,
-        }
+        })
+    }
+
+    /// Locks the session instead of showing a layer surface, e.g. for a lock screen.
+    ///
+    /// `options` is called for each output, see [`WgpuLayerShellState::lock`]. `app_creator`
+    /// gets the context of the first lock surface, so it fails if there is no output. The
+    /// compositor doesn't have to support the layer shell.
+    pub fn new_lock(
+        options: impl FnMut(&WlOutput) -> LayerShellOptions + 'static,
+        app_creator: AppCreator,
+    ) -> Result<Self> {
+        let event_loop = EventLoop::try_new().map_err(LayerShellError::from)?;
+        let layer_shell_state = WgpuLayerShellState::new_lock(event_loop.handle(), options)?;
+
+        let Some(surface) = layer_shell_state.surfaces.first() else {
+            return Err(LayerShellError::NoOutput.into());
+        };
+        let application = app_creator(surface.context())?;
+
+        Ok(Self {
+            application: RefCell::new(application),
+            event_loop,
+            layer_shell_state,
+        })
+    }
+
+    /// Connects to the compositor again and recreates the surface, keeping the app.
+    ///
+    /// Meant for [`LayerShellError::ConnectionLost`], e.g. after the compositor restarted.
+    /// The app is set up again for the new surfaces, handles taken from the old contexts
+    /// don't control the new connection. Fails if the compositor isn't available yet, so
+    /// it can be retried after a delay.
+    pub fn reconnect(&mut self, layer_shell_options: LayerShellOptions) -> Result {
+        let event_loop = EventLoop::try_new().map_err(LayerShellError::from)?;
+        let layer_shell_state = WgpuLayerShellState::new(event_loop.handle(), layer_shell_options)?;
+
+        // stops what still runs for the old state, e.g. the thread watching the theme
+        self.layer_shell_state.handle.exit();
+        // the old state is dropped before the event loop its sources are registered in
+        self.layer_shell_state = layer_shell_state;
+        self.event_loop = event_loop;
+        Ok(())
+    }
+
+    /// Tears down the surfaces and input devices, see [`WgpuLayerShellState::shutdown`].
+    ///
+    /// The app isn't drawn anymore, [`Self::reconnect`] creates new surfaces for it.
+    /// [`Self::context`] returns `None` until then.
+    pub fn shutdown(&mut self) {
+        self.layer_shell_state.shutdown();
+    }
+
+    /// Context of the first surface, e.g. to call `set_fonts` or `set_visuals` before running.
+    ///
+    /// Settings made here apply from the first frame on. Other surfaces have their own
+    /// context, see [`App::setup`] to configure all of them. `None` if there is no surface,
+    /// e.g. after [`Self::shutdown`] or once the compositor closed them.
+    pub fn context(&self) -> Option<&egui::Context> {
+        self.layer_shell_state
+            .surfaces
+            .first()
+            .map(|surface| surface.context())
+    }
+
+    /// Gives access to the state, e.g. to add more surfaces before running.
+    pub fn layer_shell_state(&mut self) -> &mut WgpuLayerShellState {
+        &mut self.layer_shell_state
     }
 
     pub fn run(&mut self) -> Result {
         loop {
-            self.event_loop
-                .dispatch(
-                    self.layer_shell_state.get_timeout(),
-                    &mut self.layer_shell_state,
-                )
-                .unwrap();
-
-            if self.layer_shell_state.should_draw() {
-                let mut application = self.application.borrow_mut();
-                self.layer_shell_state.draw(&mut **application);
+            let timeout = self.layer_shell_state.get_timeout();
+            if !self.dispatch(timeout)? {
+                return Ok(());
             }
+        }
+    }
+
+    /// Processes the pending events and draws the surfaces that are due, without blocking.
+    ///
+    /// This allows driving the app from another event loop, poll [`Self::as_fd`] to know
+    /// when events arrive and [`Self::timeout`] for the next frame. Returns `false` once the
+    /// app exited, it must not be stepped afterwards.
+    pub fn step(&mut self) -> Result<bool> {
+        self.dispatch(Some(Duration::ZERO))
+    }
 
-            if self.layer_shell_state.exit {
-                println!("exiting example");
+    /// Waits for events or the next frame like [`Self::run`], but at most `max_timeout`, and
+    /// processes them, returning control afterwards.
+    ///
+    /// Allows running periodic work between calls, the frame timeout still applies when it is
+    /// shorter. Returns `false` once the app exited, like [`Self::step`].
+    pub fn pump(&mut self, max_timeout: Option<Duration>) -> Result<bool> {
+        let timeout = match (self.layer_shell_state.get_timeout(), max_timeout) {
+            (Some(timeout), Some(max_timeout)) => Some(timeout.min(max_timeout)),
+            (timeout, max_timeout) => timeout.or(max_timeout),
+        };
+        self.dispatch(timeout)
+    }
+
+    /// Draws all shown surfaces once, bypassing the frame pacing, see
+    /// [`WgpuLayerShellState::force_draw`].
+    pub fn force_draw(&mut self) {
+        let mut application = self.application.borrow_mut();
+        self.layer_shell_state.force_draw(&mut **application);
+    }
+
+    /// Draws the shown surfaces once like [`Self::force_draw`] and returns the committed pixels
+    /// of `id`, e.g. for a thumbnail of the panel.
+    ///
+    /// Blocks until the compositor presented the frame, i.e. sent its frame callback, or
+    /// `timeout` passed, as hidden surfaces may not get one. Returns `None` if no frame was
+    /// drawn:
+    ///
+    /// - there is no surface `id`, e.g. the compositor closed it
+    /// - the surface is hidden or not configured yet, wait for its first frame
+    /// - both buffers are still held by the compositor, retry once it released one, e.g.
+    ///   after the next [`Self::step`]
+    /// - drawing failed, the error is logged
+    pub fn capture(&mut self, id: SurfaceId, timeout: Duration) -> Result<Option<Image>> {
+        let Some(surface) = self.layer_shell_state.surface_mut(id) else {
+            return Ok(None);
+        };
+        surface.capture_requested = true;
+        self.force_draw();
+        let Some(surface) = self.layer_shell_state.surface_mut(id) else {
+            return Ok(None);
+        };
+        surface.capture_requested = false;
+        let Some(image) = surface.captured.take() else {
+            return Ok(None);
+        };
+
+        let deadline = Instant::now() + timeout;
+        while self
+            .layer_shell_state
+            .surface(id)
+            .is_some_and(|surface| !surface.has_frame_callback)
+        {
+            let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
                 break;
+            };
+            // only events are processed, a frame drawn here would reset the callback state
+            self.dispatch_events(Some(remaining))?;
+        }
+        Ok(Some(image))
+    }
+
+    /// How long the event loop can sleep before the next frame is due, `None` if no frame
+    /// is scheduled.
+    pub fn timeout(&mut self) -> Option<Duration> {
+        self.layer_shell_state.get_timeout()
+    }
+
+    /// File descriptor that becomes readable when events are pending.
+    pub fn as_fd(&self) -> BorrowedFd<'_> {
+        self.event_loop.as_fd()
+    }
+
+    fn dispatch(&mut self, timeout: Option<Duration>) -> Result<bool> {
+        self.dispatch_events(timeout)?;
+
+        if self.layer_shell_state.should_draw() {
+            let mut application = self.application.borrow_mut();
+            self.layer_shell_state.draw(&mut **application);
+        }
+
+        if self.layer_shell_state.handle.exit_requested() {
+            println!("exiting example");
+            self.application.borrow_mut().on_exit();
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn dispatch_events(&mut self, timeout: Option<Duration>) -> Result {
+        if let Err(err) = self
+            .event_loop
+            .dispatch(timeout, &mut self.layer_shell_state)
+        {
+            if !self.layer_shell_state.is_connected() {
+                return Err(LayerShellError::ConnectionLost.into());
             }
+            return Err(LayerShellError::from(err).into());
         }
         Ok(())
     }
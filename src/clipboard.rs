@@ -0,0 +1,53 @@
+use std::{ffi::c_void, rc::Rc};
+
+/// Regular clipboard and primary selection backed by `smithay-clipboard`, which runs its
+/// own queue on the shared wayland connection.
+///
+/// A disabled clipboard ignores copies and never pastes anything.
+/// Clones share the same underlying clipboard.
+#[derive(Clone)]
+pub(crate) struct Clipboard {
+    inner: Option<Rc<smithay_clipboard::Clipboard>>,
+}
+
+impl Clipboard {
+    /// # Safety
+    ///
+    /// `display` must be a valid `wl_display` pointer that outlives the clipboard.
+    pub(crate) unsafe fn new(display: *mut c_void) -> Self {
+        Self {
+            inner: Some(Rc::new(smithay_clipboard::Clipboard::new(display))),
+        }
+    }
+
+    pub(crate) fn disabled() -> Self {
+        Self { inner: None }
+    }
+
+    pub(crate) fn load(&self) -> Option<String> {
+        match self.inner.as_ref()?.load() {
+            Ok(text) => Some(text),
+            Err(err) => {
+                println!("could not paste from clipboard: {err}");
+                None
+            }
+        }
+    }
+
+    /// Loads the primary selection, which is pasted with a middle click.
+    pub(crate) fn load_primary(&self) -> Option<String> {
+        match self.inner.as_ref()?.load_primary() {
+            Ok(text) => Some(text),
+            Err(err) => {
+                println!("could not paste from primary selection: {err}");
+                None
+            }
+        }
+    }
+
+    pub(crate) fn store(&self, text: impl Into<String>) {
+        if let Some(clipboard) = &self.inner {
+            clipboard.store(text);
+        }
+    }
+}
@@ -1,3 +1,8 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
 use egui::{epaint::ClippedShape, Context, FullOutput, Rect, TexturesDelta};
 
 use egui_software_backend::{
@@ -6,43 +11,132 @@ use egui_software_backend::{
 use smithay_client_toolkit::shm::slot::SlotPool;
 use wayland_client::protocol::wl_surface::WlSurface;
 
+use crate::{
+    clipboard::Clipboard,
+    layer_shell::{Clock, LayerShellError, RendererOptions},
+};
+
+/// Size of the surface a context draws, readable from [`crate::App::update`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SurfaceSize {
+    /// Size in surface-local (logical) pixels as configured by the compositor.
+    pub logical: (u32, u32),
+    /// Size of the buffer in physical pixels.
+    pub physical: (u32, u32),
+    /// Physical pixels per logical pixel.
+    pub scale: f32,
+}
+
+impl SurfaceSize {
+    /// Returns the size of the surface the context belongs to, as of the current frame.
+    pub fn from_context(ctx: &egui::Context) -> Option<Self> {
+        ctx.data(|data| data.get_temp(egui::Id::NULL))
+    }
+}
+
+/// Cost of the last frame a surface drew, e.g. to find expensive layouts.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameStats {
+    /// Time spent turning egui's shapes into primitives.
+    pub tessellate: Duration,
+    /// Time spent rasterizing the primitives into the buffer.
+    pub render: Duration,
+    pub primitives: usize,
+}
+
 pub struct State {
     context: egui::Context,
     input: egui::RawInput,
-    renderer: Renderer,
+    // one per band of the buffer that is rendered in parallel
+    renderers: Vec<Renderer>,
+    color_order: ColorFieldOrder,
+    renderer_options: RendererOptions,
+    // buffer size the cached primitives were rasterized for
+    cache_size: Option<(usize, usize)>,
+    // uploads of every live texture, replayed to new renderers when the cache is reset
+    textures: HashMap<egui::TextureId, Vec<egui::epaint::ImageDelta>>,
     start_time: std::time::Instant,
+    // replaces the time since `start_time` if set
+    clock: Option<Clock>,
+    // compositor timestamp of the first timed input event with the time it was received at
+    time_origin: Option<(u32, f64)>,
+    // when the latest button or key event happened, the next frame is timed by it
+    event_time: Option<f64>,
+    // time of the last frame
+    frame_time: f64,
     size: Option<Rect>,
+    scale: f32,
+    // pixels_per_point egui is zoomed to, independent of the scale
+    scale_override: Option<f32>,
+    clipboard: Clipboard,
+    // set while a text field wants text input
+    ime: Option<egui::output::IMEOutput>,
+    cursor_icon: egui::CursorIcon,
+    // accepts input while the surface isn't focused
+    unfocused_input: bool,
+    // opens links, nothing happens when they are clicked if unset
+    url_opener: Option<String>,
+    frame_stats: FrameStats,
+    #[cfg(feature = "accesskit")]
+    accessibility: crate::accessibility::Accessibility,
 }
 
 impl State {
-    pub fn new(context: egui::Context) -> Self {
-        let input = egui::RawInput {
-            focused: true,
+    pub fn new(
+        context: egui::Context,
+        clipboard: Clipboard,
+        color_order: ColorFieldOrder,
+        renderer_options: RendererOptions,
+    ) -> Self {
+        let mut input = egui::RawInput {
+            // set once the keyboard enters the surface
+            focused: false,
             viewport_id: egui::ViewportId::ROOT,
             ..Default::default()
         };
-
-        let renderer = Renderer::new(ColorFieldOrder::Bgra)
-            .with_convert_tris_to_rects(true)
-            .with_allow_raster_opt(true)
-            .with_caching(false);
-
-        // input
-        //     .viewports
-        //     .entry(egui::ViewportId::ROOT)
-        //     .or_default()
-        //     .native_pixels_per_point = Some(1.0);
+        input
+            .viewports
+            .entry(egui::ViewportId::ROOT)
+            .or_default()
+            .native_pixels_per_point = Some(1.0);
 
         Self {
+            #[cfg(feature = "accesskit")]
+            accessibility: crate::accessibility::Accessibility::new(&context),
             context,
             input,
-            renderer,
+            renderers: new_renderers(color_order, renderer_options),
+            color_order,
+            renderer_options,
+            cache_size: None,
+            textures: HashMap::new(),
             start_time: std::time::Instant::now(),
+            clock: None,
+            time_origin: None,
+            event_time: None,
+            frame_time: 0.0,
             size: None,
+            scale: 1.0,
+            scale_override: None,
+            clipboard,
+            ime: None,
+            cursor_icon: egui::CursorIcon::Default,
+            unfocused_input: true,
+            url_opener: None,
+            frame_stats: FrameStats::default(),
         }
     }
 
+    /// Sets the size in surface-local (logical) pixels, as configured by the compositor.
+    ///
+    /// egui's screen rect is derived from it in points for every frame, while the buffers
+    /// are allocated in physical pixels, see [`State::get_buffer_size`]. A zero dimension
+    /// keeps the previous size, there is no buffer for an empty surface.
     pub fn set_size(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            println!("set_size: ignoring {}x{}", width, height);
+            return;
+        }
         let screen_rect = egui::Rect {
             min: egui::Pos2 { x: 0f32, y: 0f32 },
             max: egui::Pos2 {
@@ -52,19 +146,128 @@ impl State {
         };
         self.size = Some(screen_rect);
         println!("set_size: {}x{}", width, height);
-        self.input.screen_rect = Some(screen_rect);
     }
 
-    pub(crate) fn get_size(&self) -> (i32, i32) {
+    /// Points per surface-local (logical) pixel.
+    ///
+    /// This is 1 unless egui zooms, i.e. its `pixels_per_point` differs from the scale.
+    fn points_per_logical_pixel(&self) -> f32 {
+        self.scale / self.context.pixels_per_point()
+    }
+
+    /// Converts surface-local coordinates, as sent by wayland input events, to egui points.
+    pub(crate) fn to_points(&self, x: f64, y: f64) -> egui::Pos2 {
+        let factor = self.points_per_logical_pixel();
+        egui::pos2(x as f32 * factor, y as f32 * factor)
+    }
+
+    /// Converts a rect in egui points to surface-local coordinates.
+    pub(crate) fn to_logical(&self, rect: egui::Rect) -> egui::Rect {
+        let factor = self.points_per_logical_pixel();
+        egui::Rect::from_min_max(
+            (rect.min.to_vec2() / factor).to_pos2(),
+            (rect.max.to_vec2() / factor).to_pos2(),
+        )
+    }
+
+    /// Logical size of the surface, `None` until a size is known.
+    pub(crate) fn get_size(&self) -> Option<(i32, i32)> {
         self.size
             .map(|r| (r.width().ceil() as i32, r.height().ceil() as i32))
-            .unwrap()
+    }
+
+    /// Size of the buffer in physical pixels.
+    pub(crate) fn get_buffer_size(&self) -> Option<(i32, i32)> {
+        let (width, height) = self.get_size()?;
+        Some((
+            (width as f32 * self.scale).round() as i32,
+            (height as f32 * self.scale).round() as i32,
+        ))
+    }
+
+    pub(crate) fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    pub(crate) fn set_scale(&mut self, scale: f32) {
+        self.scale = scale;
+        self.input
+            .viewports
+            .entry(egui::ViewportId::ROOT)
+            .or_default()
+            .native_pixels_per_point = Some(scale);
+    }
+
+    pub(crate) fn set_url_opener(&mut self, url_opener: String) {
+        self.url_opener = Some(url_opener);
+    }
+
+    pub(crate) fn set_unfocused_input(&mut self, unfocused_input: bool) {
+        self.unfocused_input = unfocused_input;
+    }
+
+    /// Drops the queued input that would be ignored while unfocused, e.g. when the focus leaves.
+    pub(crate) fn discard_unfocused_input(&mut self) {
+        if !self.unfocused_input {
+            self.input.events.retain(|event| !is_input(event));
+        }
+    }
+
+    pub(crate) fn set_scale_override(&mut self, pixels_per_point: f32) {
+        self.scale_override = Some(pixels_per_point);
     }
 
     pub(crate) fn input(&mut self) -> &mut egui::RawInput {
         &mut self.input
     }
 
+    pub(crate) fn set_clock(&mut self, clock: Clock) {
+        self.clock = Some(clock);
+    }
+
+    /// Times the next frame by an input event's compositor timestamp in milliseconds.
+    ///
+    /// egui detects double clicks and drags from the frame times, a frame drawn late would
+    /// stretch the interval between two clicks otherwise. Ignored with a custom clock.
+    pub(crate) fn set_event_time(&mut self, time_ms: u32) {
+        if self.clock.is_some() {
+            return;
+        }
+        let now = self.now();
+        // the compositor's clock has an unknown origin, so timestamps are taken relative to
+        // the first one, wrapping after 49 days like the timestamps themselves
+        let (origin_ms, origin) = *self.time_origin.get_or_insert((time_ms, now));
+        let time = origin + time_ms.wrapping_sub(origin_ms) as f64 / 1000.0;
+        self.event_time = Some(time.min(now));
+    }
+
+    fn now(&self) -> f64 {
+        match &self.clock {
+            Some(clock) => clock(),
+            None => self.start_time.elapsed().as_secs_f64(),
+        }
+    }
+
+    pub(crate) fn has_pending_events(&self) -> bool {
+        !self.input.events.is_empty()
+    }
+
+    pub(crate) fn clipboard(&self) -> &Clipboard {
+        &self.clipboard
+    }
+
+    pub(crate) fn ime(&self) -> Option<egui::output::IMEOutput> {
+        self.ime
+    }
+
+    pub(crate) fn cursor_icon(&self) -> egui::CursorIcon {
+        self.cursor_icon
+    }
+
+    pub(crate) fn frame_stats(&self) -> FrameStats {
+        self.frame_stats
+    }
+
     pub fn context(&self) -> &egui::Context {
         &self.context
     }
@@ -73,13 +276,65 @@ impl State {
         self.input.modifiers
     }
 
+    /// Queues an event for the next frame.
+    ///
+    /// Frames are only drawn once the compositor asks for one, so all events of a batch end
+    /// up in the same frame. Consecutive motion collapses to the latest position.
     pub fn push_event(&mut self, event: egui::Event) {
+        if !self.unfocused_input && !self.input.focused && is_input(&event) {
+            return;
+        }
+        if matches!(event, egui::Event::PointerMoved(_)) {
+            if let Some(last @ egui::Event::PointerMoved(_)) = self.input.events.last_mut() {
+                *last = event;
+                return;
+            }
+        }
         self.input.events.push(event);
     }
 
     pub fn process_events(&mut self, run_ui: impl FnMut(&Context)) -> FullOutput {
         // TODO: maybe we need to take input for a certain window / surface?
-        self.input.time = Some(self.start_time.elapsed().as_secs_f64());
+        self.frame_time = match self.event_time.take() {
+            // an event older than the last frame must not turn the time back
+            Some(event_time) => event_time.max(self.frame_time),
+            None => self.now(),
+        };
+        self.input.time = Some(self.frame_time);
+
+        #[cfg(feature = "accesskit")]
+        {
+            self.accessibility.set_focused(self.input.focused);
+            let action_requests = self.accessibility.take_action_requests();
+            self.input.events.extend(
+                action_requests
+                    .into_iter()
+                    .map(egui::Event::AccessKitActionRequest),
+            );
+        }
+
+        if let Some(pixels_per_point) = self.scale_override {
+            // the scale may have changed since the last frame
+            self.context.set_zoom_factor(pixels_per_point / self.scale);
+        }
+
+        // the screen rect is in points, so it depends on the current zoom
+        let factor = self.points_per_logical_pixel();
+        self.input.screen_rect = self
+            .size
+            .map(|size| egui::Rect::from_min_size(egui::Pos2::ZERO, size.size() * factor));
+
+        if let (Some((width, height)), Some((buffer_w, buffer_h))) =
+            (self.get_size(), self.get_buffer_size())
+        {
+            let size = SurfaceSize {
+                logical: (width as u32, height as u32),
+                physical: (buffer_w as u32, buffer_h as u32),
+                scale: self.scale,
+            };
+            self.context
+                .data_mut(|data| data.insert_temp(egui::Id::NULL, size));
+        }
 
         let raw_input = self.input.take();
         /* if (&raw_input.events).len() > 0 {
@@ -88,7 +343,24 @@ impl State {
         self.context.run(raw_input, run_ui)
     }
 
-    pub fn draw(&mut self, full_output: FullOutput, buffer_ref: &mut BufferMutRef) {
+    /// Renders the frame into `pixels`, a `width` × `height` buffer with 4 bytes per pixel.
+    ///
+    /// Fails without drawing if the buffer has a different size, e.g. because it was created
+    /// for a stale size.
+    pub fn draw(
+        &mut self,
+        full_output: FullOutput,
+        pixels: &mut [u8],
+        width: usize,
+        height: usize,
+    ) -> Result<(), LayerShellError> {
+        if Some(pixels.len()) != width.checked_mul(height).and_then(|len| len.checked_mul(4)) {
+            return Err(LayerShellError::BufferSize {
+                width,
+                height,
+                len: pixels.len(),
+            });
+        }
         //self.context.set_pixels_per_point(screen_descriptor.pixels_per_point);
 
         // iterate over viewport outputs
@@ -98,19 +370,198 @@ impl State {
 
         //dbg!(&full_output.);
 
-        // TODO: implement platform output handling
-        // this is for things like clipboard support
-        //self.state.handle_platform_output(window, full_output.platform_output);
+        self.handle_platform_output(&full_output.platform_output);
 
+        let tessellate_start = Instant::now();
         let clipped_primitives = self
             .context
             .tessellate(full_output.shapes, full_output.pixels_per_point);
+        self.frame_stats.tessellate = tessellate_start.elapsed();
+        self.frame_stats.primitives = clipped_primitives.len();
+
+        let mut textures_delta = full_output.textures_delta;
+        if self.renderer_options.caching {
+            self.track_textures(&textures_delta);
+            let buffer_size = Some((width, height));
+            if self.cache_size.is_some() && self.cache_size != buffer_size {
+                // the cached primitives were rasterized for the previous buffer, new
+                // renderers start with an empty cache but have to get the textures again
+                self.renderers = new_renderers(self.color_order, self.renderer_options);
+                textures_delta.set = self
+                    .textures
+                    .iter()
+                    .flat_map(|(id, deltas)| deltas.iter().map(|delta| (*id, delta.clone())))
+                    .collect();
+            }
+            self.cache_size = buffer_size;
+        }
 
-        self.renderer.render(
-            buffer_ref,
+        let render_start = Instant::now();
+        self.render(
             &clipped_primitives,
-            &full_output.textures_delta,
+            &textures_delta,
             full_output.pixels_per_point,
+            pixels,
+            width,
+            height,
         );
+        self.frame_stats.render = render_start.elapsed();
+        Ok(())
+    }
+
+    fn render(
+        &mut self,
+        clipped_primitives: &[egui::ClippedPrimitive],
+        textures_delta: &TexturesDelta,
+        pixels_per_point: f32,
+        pixels: &mut [u8],
+        width: usize,
+        height: usize,
+    ) {
+        if let [renderer] = self.renderers.as_mut_slice() {
+            let buffer_ref =
+                &mut BufferMutRef::new(bytemuck::cast_slice_mut(pixels), width, height);
+            renderer.render(
+                buffer_ref,
+                clipped_primitives,
+                textures_delta,
+                pixels_per_point,
+            );
+            return;
+        }
+
+        // every renderer gets a band of rows, the bands don't overlap, so they can be written
+        // at the same time. Bands may be empty, the renderer still has to get the textures.
+        let bands = self.renderers.len();
+        let mut rest = pixels;
+        std::thread::scope(|scope| {
+            for (index, renderer) in self.renderers.iter_mut().enumerate() {
+                let top = height * index / bands;
+                let rows = height * (index + 1) / bands - top;
+                let (band, remaining) = std::mem::take(&mut rest).split_at_mut(rows * width * 4);
+                rest = remaining;
+
+                let band_rect = Rect::from_min_size(
+                    egui::pos2(0.0, top as f32 / pixels_per_point),
+                    egui::vec2(width as f32, rows as f32) / pixels_per_point,
+                );
+                scope.spawn(move || {
+                    let clipped_primitives = band_primitives(clipped_primitives, band_rect);
+                    let buffer_ref =
+                        &mut BufferMutRef::new(bytemuck::cast_slice_mut(band), width, rows);
+                    renderer.render(
+                        buffer_ref,
+                        &clipped_primitives,
+                        textures_delta,
+                        pixels_per_point,
+                    );
+                });
+            }
+        });
     }
+
+    fn track_textures(&mut self, textures_delta: &TexturesDelta) {
+        for (id, delta) in &textures_delta.set {
+            if delta.is_whole() {
+                self.textures.insert(*id, vec![delta.clone()]);
+            } else if let Some(deltas) = self.textures.get_mut(id) {
+                deltas.push(delta.clone());
+            }
+        }
+        for id in &textures_delta.free {
+            self.textures.remove(id);
+        }
+    }
+
+    fn handle_platform_output(&mut self, platform_output: &egui::PlatformOutput) {
+        self.ime = platform_output.ime;
+        self.cursor_icon = platform_output.cursor_icon;
+
+        #[cfg(feature = "accesskit")]
+        if let Some(update) = &platform_output.accesskit_update {
+            self.accessibility.update(update.clone());
+        }
+
+        for command in &platform_output.commands {
+            match command {
+                egui::OutputCommand::CopyText(text) => self.clipboard.store(text.clone()),
+                egui::OutputCommand::OpenUrl(open_url) => self.open_url(&open_url.url),
+                _ => {}
+            }
+        }
+    }
+
+    fn open_url(&self, url: &str) {
+        let Some(url_opener) = &self.url_opener else {
+            return;
+        };
+        let mut args = url_opener.split_whitespace();
+        let Some(program) = args.next() else {
+            return;
+        };
+        match std::process::Command::new(program)
+            .args(args)
+            .arg(url)
+            .spawn()
+        {
+            // waited for on another thread, so it doesn't stay around as a zombie
+            Ok(mut child) => {
+                std::thread::spawn(move || child.wait());
+            }
+            Err(err) => println!("could not open {url} with {program}: {err}"),
+        }
+    }
+}
+
+/// Whether the event is input a surface ignores while unfocused, releases and
+/// [`egui::Event::PointerGone`] aren't, so no key or button stays down.
+fn is_input(event: &egui::Event) -> bool {
+    use egui::Event;
+    match event {
+        Event::Key { pressed, .. } | Event::PointerButton { pressed, .. } => *pressed,
+        Event::Touch { phase, .. } => {
+            matches!(phase, egui::TouchPhase::Start | egui::TouchPhase::Move)
+        }
+        Event::PointerMoved(_)
+        | Event::MouseMoved(_)
+        | Event::MouseWheel { .. }
+        | Event::Zoom(_)
+        | Event::Text(_)
+        | Event::Paste(_)
+        | Event::Copy
+        | Event::Cut
+        | Event::Ime(_) => true,
+        _ => false,
+    }
+}
+
+fn new_renderers(color_order: ColorFieldOrder, options: RendererOptions) -> Vec<Renderer> {
+    (0..options.threads.max(1))
+        .map(|_| {
+            Renderer::new(color_order)
+                .with_convert_tris_to_rects(options.convert_tris_to_rects)
+                .with_allow_raster_opt(options.allow_raster_opt)
+                .with_caching(options.caching)
+        })
+        .collect()
+}
+
+/// Clips the primitives to a band and moves them so the band's top edge is at 0.
+fn band_primitives(
+    clipped_primitives: &[egui::ClippedPrimitive],
+    band_rect: Rect,
+) -> Vec<egui::ClippedPrimitive> {
+    let offset = -band_rect.min.to_vec2();
+    clipped_primitives
+        .iter()
+        .filter(|primitive| primitive.clip_rect.intersects(band_rect))
+        .map(|primitive| {
+            let mut primitive = primitive.clone();
+            primitive.clip_rect = primitive.clip_rect.intersect(band_rect).translate(offset);
+            if let egui::epaint::Primitive::Mesh(mesh) = &mut primitive.primitive {
+                mesh.translate(offset);
+            }
+            primitive
+        })
+        .collect()
 }
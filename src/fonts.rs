@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+use egui::epaint::text::{FontInsert, FontPriority, InsertFontFamily};
+
+/// Adds the font files to the context, preferred over egui's fonts in the given order.
+///
+/// Files that can't be read or aren't TrueType/OpenType fonts are skipped, egui would panic
+/// while building the font atlas otherwise.
+pub(crate) fn install(ctx: &egui::Context, paths: &[PathBuf]) {
+    // inserted with the highest priority, so the first path has to be inserted last
+    for path in paths.iter().rev() {
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(err) => {
+                println!("could not read font {}: {err}", path.display());
+                continue;
+            }
+        };
+        if !is_font(&data) {
+            println!("{} is not a TrueType or OpenType font", path.display());
+            continue;
+        }
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+
+        ctx.add_font(FontInsert::new(
+            &name,
+            egui::FontData::from_owned(data),
+            [egui::FontFamily::Proportional, egui::FontFamily::Monospace]
+                .into_iter()
+                .map(|family| InsertFontFamily {
+                    family,
+                    priority: FontPriority::Highest,
+                })
+                .collect(),
+        ));
+    }
+}
+
+/// Checks the signature of TrueType, OpenType and font collection files.
+fn is_font(data: &[u8]) -> bool {
+    [b"\0\x01\0\0", b"OTTO", b"true", b"ttcf"]
+        .iter()
+        .any(|signature| data.starts_with(*signature))
+}
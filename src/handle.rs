@@ -0,0 +1,115 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+use smithay_client_toolkit::reexports::calloop::ping::Ping;
+
+/// Cheap to clone handle to control the running layer shell app, e.g. from [`crate::App::update`].
+#[derive(Clone, Default)]
+pub struct LayerShellHandle {
+    exit: Arc<AtomicBool>,
+    // weak, so closed surfaces don't stay alive through the handle
+    draw_requests: Arc<Mutex<Vec<Weak<RwLock<Option<Instant>>>>>>,
+    // surfaces keep redrawing every frame until then
+    animate_until: Arc<Mutex<Option<Instant>>>,
+    // color scheme of the desktop, `None` if unknown
+    system_theme: Arc<Mutex<Option<egui::Theme>>>,
+    // wakes the event loop, so requests from other threads are handled right away
+    ping: Option<Ping>,
+}
+
+impl LayerShellHandle {
+    /// Returns the handle of the app the context belongs to.
+    pub fn from_context(ctx: &egui::Context) -> Option<Self> {
+        ctx.data(|data| data.get_temp(egui::Id::NULL))
+    }
+
+    pub(crate) fn new(ping: Ping) -> Self {
+        Self {
+            ping: Some(ping),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn install(&self, ctx: &egui::Context) {
+        ctx.data_mut(|data| data.insert_temp(egui::Id::NULL, self.clone()));
+    }
+
+    pub(crate) fn add_draw_request(&self, draw_request: &Arc<RwLock<Option<Instant>>>) {
+        let mut draw_requests = self.draw_requests.lock().unwrap();
+        draw_requests.retain(|draw_request| draw_request.strong_count() > 0);
+        draw_requests.push(Arc::downgrade(draw_request));
+    }
+
+    /// Redraws all surfaces as soon as possible, can be called from any thread.
+    ///
+    /// Use [`egui::Context::request_repaint`] to only redraw a single surface.
+    pub fn request_redraw(&self) {
+        let now = Instant::now();
+        for draw_request in self.draw_requests.lock().unwrap().iter() {
+            if let Some(draw_request) = draw_request.upgrade() {
+                *draw_request.write().unwrap() = Some(now);
+            }
+        }
+        self.wake();
+    }
+
+    /// Redraws all surfaces every frame for `duration`, e.g. for a pulsing indicator.
+    ///
+    /// The frames are paced by the compositor and [`crate::layer_shell::LayerShellOptions::max_fps`].
+    /// Calling it again while animating extends the animation if it ends later.
+    pub fn animate_for(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+        {
+            let mut animate_until = self.animate_until.lock().unwrap();
+            if animate_until.is_none_or(|animate_until| animate_until < until) {
+                *animate_until = Some(until);
+            }
+        }
+        self.request_redraw();
+    }
+
+    pub(crate) fn is_animating(&self) -> bool {
+        let mut animate_until = self.animate_until.lock().unwrap();
+        if animate_until.is_some_and(|until| until <= Instant::now()) {
+            *animate_until = None;
+        }
+        animate_until.is_some()
+    }
+
+    /// Sets the theme egui follows with [`egui::ThemePreference::System`], e.g. from a
+    /// desktop specific source, and redraws all surfaces.
+    ///
+    /// It is read from the xdg desktop portal if the `portal` feature is enabled, otherwise
+    /// from `GTK_THEME`.
+    pub fn set_system_theme(&self, theme: Option<egui::Theme>) {
+        *self.system_theme.lock().unwrap() = theme;
+        // the loop redraws the surfaces, except the ones still holding back their first frame
+        self.wake();
+    }
+
+    pub(crate) fn system_theme(&self) -> Option<egui::Theme> {
+        *self.system_theme.lock().unwrap()
+    }
+
+    /// Interrupts the event loop if it is waiting for events.
+    pub(crate) fn wake(&self) {
+        if let Some(ping) = &self.ping {
+            ping.ping();
+        }
+    }
+
+    /// Stops the event loop after the current frame.
+    pub fn exit(&self) {
+        self.exit.store(true, Ordering::Relaxed);
+        self.wake();
+    }
+
+    pub fn exit_requested(&self) -> bool {
+        self.exit.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,279 @@
+use std::sync::Arc;
+
+use egui_software_backend::ColorFieldOrder;
+
+use crate::{
+    clipboard::Clipboard,
+    egui_state,
+    layer_shell::{BufferFormat, LayerShellError, RendererOptions, SurfaceId},
+    App, FrameContext, FrameStats,
+};
+
+/// Pixels of a frame rendered by [`Headless`] or [`render_to_image`].
+pub struct Image {
+    /// Width in physical pixels.
+    pub width: u32,
+    /// Height in physical pixels.
+    pub height: u32,
+    /// Premultiplied RGBA, row by row without padding, whatever the [`BufferFormat`] the frame
+    /// was rendered for.
+    pub pixels: Vec<u8>,
+}
+
+/// Drives an [`App`] without a wayland connection, e.g. for integration tests.
+///
+/// Input is fed as synthetic events, every [`Headless::step`] runs the app and renders a
+/// frame into an owned buffer. [`Headless::step_into`] renders into a buffer owned by the
+/// caller instead, e.g. to embed the UI into a compositor's own framebuffer. The clipboard
+/// is disabled.
+pub struct Headless {
+    egui_state: egui_state::State,
+    image: Image,
+    // order of the channels `step_into` renders
+    color_order: ColorFieldOrder,
+    needs_setup: bool,
+}
+
+impl Headless {
+    /// `width` and `height` are the logical size, frames are `pixels_per_point` times larger.
+    ///
+    /// Fails with [`LayerShellError::ZeroWidth`] or [`LayerShellError::ZeroHeight`] for an
+    /// empty size, there is nothing to render into.
+    pub fn new(width: u32, height: u32, pixels_per_point: f32) -> Result<Self, LayerShellError> {
+        Self::with_color_order(width, height, pixels_per_point, ColorFieldOrder::Rgba)
+    }
+
+    /// Like [`Headless::new`], but renders the channels in the order of `format`, for
+    /// [`Headless::step_into`] a buffer of that format. [`Headless::step`] still returns RGBA.
+    pub fn with_format(
+        width: u32,
+        height: u32,
+        pixels_per_point: f32,
+        format: BufferFormat,
+    ) -> Result<Self, LayerShellError> {
+        Self::with_color_order(width, height, pixels_per_point, format.color_order())
+    }
+
+    fn with_color_order(
+        width: u32,
+        height: u32,
+        pixels_per_point: f32,
+        color_order: ColorFieldOrder,
+    ) -> Result<Self, LayerShellError> {
+        check_size(width, height)?;
+        let mut egui_state = egui_state::State::new(
+            egui::Context::default(),
+            Clipboard::disabled(),
+            color_order,
+            RendererOptions::default(),
+        );
+        egui_state.set_size(width, height);
+        egui_state.set_scale(pixels_per_point);
+        // there is no keyboard focus to wait for
+        egui_state.input().focused = true;
+
+        Ok(Self {
+            egui_state,
+            image: Image {
+                width: 0,
+                height: 0,
+                pixels: Vec::new(),
+            },
+            color_order,
+            needs_setup: true,
+        })
+    }
+
+    pub fn context(&self) -> &egui::Context {
+        self.egui_state.context()
+    }
+
+    /// Changes the logical size from the next frame on, e.g. when the embedding area resized.
+    ///
+    /// Fails like [`Headless::new`] for an empty size, the previous size is kept then.
+    pub fn set_size(&mut self, width: u32, height: u32) -> Result<(), LayerShellError> {
+        check_size(width, height)?;
+        self.egui_state.set_size(width, height);
+        Ok(())
+    }
+
+    /// Size of the frames in physical pixels.
+    pub fn frame_size(&self) -> (u32, u32) {
+        let (width, height) = self
+            .egui_state
+            .get_buffer_size()
+            .expect("size is set on creation");
+        (width as u32, height as u32)
+    }
+
+    /// Input of the next frame, e.g. to set the modifiers or the time.
+    pub fn input(&mut self) -> &mut egui::RawInput {
+        self.egui_state.input()
+    }
+
+    /// Replaces the wall clock, e.g. to advance time by a fixed step every frame.
+    pub fn set_clock(&mut self, clock: impl Fn() -> f64 + Send + Sync + 'static) {
+        self.egui_state.set_clock(Arc::new(clock));
+    }
+
+    pub fn push_event(&mut self, event: egui::Event) {
+        self.egui_state.push_event(event);
+    }
+
+    /// Runs a frame of `app` with the pending input and returns the rendered image.
+    pub fn step(&mut self, app: &mut dyn App) -> &Image {
+        let (width, height) = self.frame_size();
+        let mut pixels = std::mem::take(&mut self.image.pixels);
+        // every frame is rendered from scratch, like the cleared wayland buffers
+        pixels.clear();
+        pixels.resize(width as usize * height as usize * 4, 0);
+
+        self.step_into(app, &mut pixels)
+            .expect("the image is resized to the frame");
+        to_rgba(&mut pixels, self.color_order);
+
+        self.image = Image {
+            width,
+            height,
+            pixels,
+        };
+        &self.image
+    }
+
+    /// Runs a frame of `app` and renders it into `pixels`, a buffer of [`Headless::frame_size`]
+    /// with 4 bytes per pixel and no padding between rows.
+    ///
+    /// The frame is blended over the buffer's content, clear it first to only get the UI.
+    /// [`Headless::image`] isn't updated. Fails without running the app if the buffer has a
+    /// different size.
+    pub fn step_into(
+        &mut self,
+        app: &mut dyn App,
+        pixels: &mut [u8],
+    ) -> Result<(), LayerShellError> {
+        let (width, height) = self.frame_size();
+        if width as usize * height as usize * 4 != pixels.len() {
+            return Err(LayerShellError::BufferSize {
+                width: width as usize,
+                height: height as usize,
+                len: pixels.len(),
+            });
+        }
+
+        if self.needs_setup {
+            self.needs_setup = false;
+            app.setup(self.egui_state.context());
+        }
+
+        self.egui_state
+            .input()
+            .events
+            .retain(|event| !app.raw_event(event));
+        let (logical_width, logical_height) =
+            self.egui_state.get_size().expect("size is set on creation");
+        // a headless app acts like the first surface, which isn't on any output
+        let frame = FrameContext {
+            surface: SurfaceId(0),
+            logical_size: (logical_width as u32, logical_height as u32),
+            scale: self.egui_state.scale(),
+            output_name: None,
+        };
+        let full_output = self
+            .egui_state
+            .process_events(|ctx| app.update_frame(&frame, ctx));
+
+        self.egui_state
+            .draw(full_output, pixels, width as usize, height as usize)
+    }
+
+    /// Timings and primitive count of the last [`Headless::step`].
+    pub fn last_frame_stats(&self) -> FrameStats {
+        self.egui_state.frame_stats()
+    }
+
+    /// The image rendered by the last [`Headless::step`].
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+}
+
+/// Swaps the red and blue channels of BGRA pixels, so [`Image`]s are always RGBA.
+pub(crate) fn to_rgba(pixels: &mut [u8], color_order: ColorFieldOrder) {
+    if matches!(color_order, ColorFieldOrder::Bgra) {
+        for pixel in pixels.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+    }
+}
+
+/// Renders a single frame of `app` without a compositor, e.g. for snapshot tests.
+///
+/// `width` and `height` are the logical size, the image is `pixels_per_point` times larger.
+/// The app is set up with a fresh context before its frame, the clipboard is disabled.
+/// Fails like [`Headless::new`] for an empty size.
+pub fn render_to_image(
+    app: &mut dyn App,
+    width: u32,
+    height: u32,
+    pixels_per_point: f32,
+) -> Result<Image, LayerShellError> {
+    let mut headless = Headless::new(width, height, pixels_per_point)?;
+    headless.step(app);
+    Ok(headless.image)
+}
+
+fn check_size(width: u32, height: u32) -> Result<(), LayerShellError> {
+    if width == 0 {
+        return Err(LayerShellError::ZeroWidth);
+    }
+    if height == 0 {
+        return Err(LayerShellError::ZeroHeight);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct RoundedPanel;
+
+    impl App for RoundedPanel {
+        fn update(&mut self, ctx: &egui::Context) {
+            // like examples/rounded_panel.rs, but opaque so the center is fully covered
+            let frame = egui::Frame::new()
+                .fill(egui::Color32::from_rgb(30, 30, 30))
+                .corner_radius(16);
+            egui::CentralPanel::default()
+                .frame(frame)
+                .show(ctx, |_ui| {});
+        }
+    }
+
+    fn alpha(image: &Image, x: u32, y: u32) -> u8 {
+        image.pixels[((y * image.width + x) * 4 + 3) as usize]
+    }
+
+    #[test]
+    fn rounded_frame_leaves_corners_transparent() {
+        let image = render_to_image(&mut RoundedPanel, 200, 100, 1.0).unwrap();
+        let (right, bottom) = (image.width - 1, image.height - 1);
+
+        for (x, y) in [(0, 0), (right, 0), (0, bottom), (right, bottom)] {
+            assert_eq!(alpha(&image, x, y), 0, "corner at {x}, {y}");
+        }
+        assert_eq!(alpha(&image, image.width / 2, image.height / 2), 255);
+    }
+
+    #[test]
+    fn empty_size_is_rejected() {
+        assert!(matches!(
+            Headless::new(0, 10, 1.0),
+            Err(LayerShellError::ZeroWidth)
+        ));
+        assert!(matches!(
+            render_to_image(&mut RoundedPanel, 10, 0, 1.0),
+            Err(LayerShellError::ZeroHeight)
+        ));
+    }
+}
@@ -0,0 +1,63 @@
+use std::ffi::OsString;
+
+use smithay_client_toolkit::seat::keyboard::Keysym;
+use xkbcommon::xkb::{self, compose};
+
+/// Outcome of feeding a pressed key to the compose state.
+pub(crate) enum ComposeResult {
+    /// The key isn't part of a sequence, it types its own text.
+    Pass,
+    /// The key started, continued or cancelled a sequence, e.g. a dead key, nothing is typed.
+    Pending,
+    /// The sequence finished and types this text.
+    Composed(String),
+}
+
+/// Dead keys and compose sequences of the user's locale.
+pub(crate) struct Compose {
+    state: compose::State,
+}
+
+impl Compose {
+    /// Loads the compose table of the locale, `None` if there is none.
+    pub(crate) fn new() -> Option<Self> {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .into_iter()
+            .filter_map(std::env::var_os)
+            .find(|locale| !locale.is_empty())
+            .unwrap_or_else(|| OsString::from("C"));
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let table = compose::Table::new_from_locale(&context, &locale, compose::COMPILE_NO_FLAGS)
+            .map_err(|_| println!("no compose table for locale {locale:?}"))
+            .ok()?;
+        Some(Self {
+            state: compose::State::new(&table, compose::STATE_NO_FLAGS),
+        })
+    }
+
+    pub(crate) fn feed(&mut self, keysym: Keysym) -> ComposeResult {
+        // e.g. modifiers are ignored and don't interrupt a sequence
+        if matches!(self.state.feed(keysym), compose::FeedResult::Ignored) {
+            return ComposeResult::Pass;
+        }
+        match self.state.status() {
+            compose::Status::Nothing => ComposeResult::Pass,
+            compose::Status::Composing => ComposeResult::Pending,
+            compose::Status::Composed => {
+                let text = self.state.utf8().unwrap_or_default();
+                self.state.reset();
+                ComposeResult::Composed(text)
+            }
+            // an invalid sequence swallows the key that broke it, like other toolkits do
+            compose::Status::Cancelled => {
+                self.state.reset();
+                ComposeResult::Pending
+            }
+        }
+    }
+
+    /// Drops an unfinished sequence, e.g. when the focus moves to another window.
+    pub(crate) fn reset(&mut self) {
+        self.state.reset();
+    }
+}
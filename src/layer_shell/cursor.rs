@@ -0,0 +1,81 @@
+use smithay_client_toolkit::seat::pointer::CursorIcon;
+
+use super::WgpuLayerShellState;
+
+impl WgpuLayerShellState {
+    /// Shows the cursor egui requested for the surface the pointer is over.
+    ///
+    /// Uses cursor-shape-v1 if the compositor supports it, otherwise the images of the xcursor
+    /// theme named by `XCURSOR_THEME` in the size of `XCURSOR_SIZE`, attached to the pointer's
+    /// own surface.
+    pub(crate) fn update_cursor(&mut self) {
+        let Some(pointer) = &self.pointer else {
+            return;
+        };
+        let Some(surface) = self.pointer_focus.and_then(|id| self.surface(id)) else {
+            return;
+        };
+        let cursor_icon = surface.egui_state.cursor_icon();
+        if self.cursor_icon == Some(cursor_icon) {
+            return;
+        }
+        self.cursor_icon = Some(cursor_icon);
+
+        let result = match translate_cursor_icon(cursor_icon) {
+            // older xcursor themes lack some of the shapes, the default arrow is better
+            // than keeping whatever cursor was shown before
+            Some(cursor_icon) => pointer
+                .set_cursor(&self.connection, cursor_icon)
+                .or_else(|_| pointer.set_cursor(&self.connection, CursorIcon::Default)),
+            None => pointer.hide_cursor(),
+        };
+        if let Err(err) = result {
+            println!("could not set cursor: {err}");
+        }
+    }
+}
+
+fn translate_cursor_icon(cursor_icon: egui::CursorIcon) -> Option<CursorIcon> {
+    let cursor_icon = match cursor_icon {
+        egui::CursorIcon::None => return None,
+
+        egui::CursorIcon::Default => CursorIcon::Default,
+        egui::CursorIcon::ContextMenu => CursorIcon::ContextMenu,
+        egui::CursorIcon::Help => CursorIcon::Help,
+        egui::CursorIcon::PointingHand => CursorIcon::Pointer,
+        egui::CursorIcon::Progress => CursorIcon::Progress,
+        egui::CursorIcon::Wait => CursorIcon::Wait,
+        egui::CursorIcon::Cell => CursorIcon::Cell,
+        egui::CursorIcon::Crosshair => CursorIcon::Crosshair,
+        egui::CursorIcon::Text => CursorIcon::Text,
+        egui::CursorIcon::VerticalText => CursorIcon::VerticalText,
+        egui::CursorIcon::Alias => CursorIcon::Alias,
+        egui::CursorIcon::Copy => CursorIcon::Copy,
+        egui::CursorIcon::Move => CursorIcon::Move,
+        egui::CursorIcon::NoDrop => CursorIcon::NoDrop,
+        egui::CursorIcon::NotAllowed => CursorIcon::NotAllowed,
+        egui::CursorIcon::Grab => CursorIcon::Grab,
+        egui::CursorIcon::Grabbing => CursorIcon::Grabbing,
+        egui::CursorIcon::AllScroll => CursorIcon::AllScroll,
+
+        egui::CursorIcon::ResizeHorizontal => CursorIcon::EwResize,
+        egui::CursorIcon::ResizeNeSw => CursorIcon::NeswResize,
+        egui::CursorIcon::ResizeNwSe => CursorIcon::NwseResize,
+        egui::CursorIcon::ResizeVertical => CursorIcon::NsResize,
+
+        egui::CursorIcon::ResizeEast => CursorIcon::EResize,
+        egui::CursorIcon::ResizeSouthEast => CursorIcon::SeResize,
+        egui::CursorIcon::ResizeSouth => CursorIcon::SResize,
+        egui::CursorIcon::ResizeSouthWest => CursorIcon::SwResize,
+        egui::CursorIcon::ResizeWest => CursorIcon::WResize,
+        egui::CursorIcon::ResizeNorthWest => CursorIcon::NwResize,
+        egui::CursorIcon::ResizeNorth => CursorIcon::NResize,
+        egui::CursorIcon::ResizeNorthEast => CursorIcon::NeResize,
+        egui::CursorIcon::ResizeColumn => CursorIcon::ColResize,
+        egui::CursorIcon::ResizeRow => CursorIcon::RowResize,
+
+        egui::CursorIcon::ZoomIn => CursorIcon::ZoomIn,
+        egui::CursorIcon::ZoomOut => CursorIcon::ZoomOut,
+    };
+    Some(cursor_icon)
+}
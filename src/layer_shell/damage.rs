@@ -0,0 +1,147 @@
+/// Keeps a copy of the last frame to only damage the regions that changed since.
+#[derive(Default)]
+pub(crate) struct DamageTracker {
+    previous: Vec<u8>,
+    width: i32,
+    height: i32,
+}
+
+impl DamageTracker {
+    // rows are compared in bands, each band yields at most one rectangle
+    const BAND_HEIGHT: usize = 32;
+
+    /// Returns the rectangles `(x, y, width, height)` of `frame` that differ from the
+    /// previous frame, in buffer coordinates.
+    ///
+    /// The whole frame is damaged if the size changed.
+    pub(crate) fn damage(
+        &mut self,
+        frame: &[u8],
+        width: i32,
+        height: i32,
+    ) -> Vec<(i32, i32, i32, i32)> {
+        if self.width != width || self.height != height || self.previous.len() != frame.len() {
+            self.width = width;
+            self.height = height;
+            self.previous.clear();
+            self.previous.extend_from_slice(frame);
+            return vec![(0, 0, width, height)];
+        }
+
+        let stride = width as usize * 4;
+        let band_size = stride * Self::BAND_HEIGHT;
+        let mut damage = Vec::new();
+
+        for (band, (rows, previous_rows)) in frame
+            .chunks(band_size)
+            .zip(self.previous.chunks(band_size))
+            .enumerate()
+        {
+            let mut changed: Option<(usize, usize)> = None;
+            for (row, previous_row) in rows
+                .chunks_exact(stride)
+                .zip(previous_rows.chunks_exact(stride))
+            {
+                if row == previous_row {
+                    continue;
+                }
+                let mut diff = row.iter().zip(previous_row).map(|(a, b)| a != b);
+                let first = diff.clone().position(|changed| changed).unwrap_or(0) / 4;
+                let last = diff.rposition(|changed| changed).unwrap_or(0) / 4;
+                changed = Some(match changed {
+                    Some((min_x, max_x)) => (min_x.min(first), max_x.max(last)),
+                    None => (first, last),
+                });
+            }
+
+            if let Some((min_x, max_x)) = changed {
+                damage.push((
+                    min_x as i32,
+                    (band * Self::BAND_HEIGHT) as i32,
+                    (max_x - min_x + 1) as i32,
+                    (rows.len() / stride) as i32,
+                ));
+            }
+        }
+
+        self.previous.copy_from_slice(frame);
+        damage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: i32 = 64;
+    const HEIGHT: i32 = 96;
+
+    fn frame(width: i32, height: i32) -> Vec<u8> {
+        vec![0; width as usize * height as usize * 4]
+    }
+
+    fn set_pixel(frame: &mut [u8], width: i32, x: usize, y: usize) {
+        let offset = (y * width as usize + x) * 4;
+        frame[offset..offset + 4].copy_from_slice(&[255; 4]);
+    }
+
+    #[test]
+    fn unchanged_frame_has_no_damage() {
+        let mut tracker = DamageTracker::default();
+        let frame = frame(WIDTH, HEIGHT);
+        assert_eq!(
+            tracker.damage(&frame, WIDTH, HEIGHT),
+            [(0, 0, WIDTH, HEIGHT)]
+        );
+        assert!(tracker.damage(&frame, WIDTH, HEIGHT).is_empty());
+    }
+
+    #[test]
+    fn changes_damage_their_band() {
+        let mut tracker = DamageTracker::default();
+        let mut frame = frame(WIDTH, HEIGHT);
+        tracker.damage(&frame, WIDTH, HEIGHT);
+
+        set_pixel(&mut frame, WIDTH, 10, 40);
+        assert_eq!(tracker.damage(&frame, WIDTH, HEIGHT), [(10, 32, 1, 32)]);
+
+        // changes in the same band are merged into one rectangle spanning them
+        set_pixel(&mut frame, WIDTH, 5, 33);
+        set_pixel(&mut frame, WIDTH, 20, 60);
+        assert_eq!(tracker.damage(&frame, WIDTH, HEIGHT), [(5, 32, 16, 32)]);
+    }
+
+    #[test]
+    fn last_band_is_cut_to_the_frame() {
+        let mut tracker = DamageTracker::default();
+        let mut frame = frame(WIDTH, 40);
+        tracker.damage(&frame, WIDTH, 40);
+
+        set_pixel(&mut frame, WIDTH, 0, 35);
+        assert_eq!(tracker.damage(&frame, WIDTH, 40), [(0, 32, 1, 8)]);
+    }
+
+    #[test]
+    fn resize_damages_everything() {
+        let mut tracker = DamageTracker::default();
+        tracker.damage(&frame(WIDTH, HEIGHT), WIDTH, HEIGHT);
+        assert_eq!(
+            tracker.damage(&frame(WIDTH * 2, HEIGHT), WIDTH * 2, HEIGHT),
+            [(0, 0, WIDTH * 2, HEIGHT)]
+        );
+    }
+
+    #[test]
+    fn reset_after_hide_damages_everything() {
+        let mut tracker = DamageTracker::default();
+        let frame = frame(WIDTH, HEIGHT);
+        tracker.damage(&frame, WIDTH, HEIGHT);
+
+        // what `LayerShellSurface::hide` does, the compositor forgot the content
+        tracker = DamageTracker::default();
+        assert_eq!(
+            tracker.damage(&frame, WIDTH, HEIGHT),
+            [(0, 0, WIDTH, HEIGHT)]
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use std::{
+    ffi::OsString,
+    io::{ErrorKind, Read},
+    os::unix::ffi::OsStringExt,
+    path::PathBuf,
+};
+
+use smithay_client_toolkit::{
+    data_device_manager::{
+        data_device::DataDeviceHandler,
+        data_offer::{DataOfferHandler, DragOffer},
+        data_source::DataSourceHandler,
+        WritePipe,
+    },
+    delegate_data_device,
+    reexports::calloop::PostAction,
+};
+use wayland_client::{
+    protocol::{
+        wl_data_device::WlDataDevice, wl_data_device_manager::DndAction,
+        wl_data_source::WlDataSource, wl_surface::WlSurface,
+    },
+    Connection, QueueHandle,
+};
+
+use super::{LayerShellSurface, WgpuLayerShellState};
+
+const URI_LIST_MIME: &str = "text/uri-list";
+
+delegate_data_device!(WgpuLayerShellState);
+
+impl WgpuLayerShellState {
+    fn drag_surface_mut(&mut self) -> Option<&mut LayerShellSurface> {
+        self.drag_surface.and_then(|id| self.surface_mut(id))
+    }
+
+    fn clear_hovered_files(&mut self) {
+        if let Some(surface) = self.drag_surface_mut() {
+            surface.egui_state.input().hovered_files.clear();
+            surface.request_redraw();
+        }
+        self.drag_surface = None;
+    }
+}
+
+impl DataDeviceHandler for WgpuLayerShellState {
+    fn enter(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _data_device: &WlDataDevice,
+        x: f64,
+        y: f64,
+        wl_surface: &WlSurface,
+    ) {
+        let Some(offer) = self
+            .data_device
+            .as_ref()
+            .and_then(|data_device| data_device.data().drag_offer())
+        else {
+            return;
+        };
+        let Some(surface) = self.surface_by_wl_mut(wl_surface) else {
+            return;
+        };
+
+        // only files are accepted, everything else is rejected by accepting no mime type
+        if !offer.with_mime_types(|mime_types| mime_types.iter().any(|mime| mime == URI_LIST_MIME))
+        {
+            offer.accept_mime_type(offer.serial, None);
+            return;
+        }
+        offer.accept_mime_type(offer.serial, Some(URI_LIST_MIME.to_owned()));
+        offer.set_actions(DndAction::Copy, DndAction::Copy);
+
+        let id = surface.id;
+        let pos = surface.egui_state.to_points(x, y);
+        let input = surface.egui_state.input();
+        // the paths are only known once the data is received on drop
+        input.hovered_files = vec![egui::HoveredFile {
+            path: None,
+            mime: URI_LIST_MIME.to_owned(),
+        }];
+        surface
+            .egui_state
+            .push_event(egui::Event::PointerMoved(pos));
+        surface.request_redraw();
+        self.drag_surface = Some(id);
+    }
+
+    fn leave(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _data_device: &WlDataDevice) {
+        self.clear_hovered_files();
+    }
+
+    fn motion(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _data_device: &WlDataDevice,
+        x: f64,
+        y: f64,
+    ) {
+        // the pointer doesn't send motion while dragging, so drop targets can still be hovered
+        if let Some(surface) = self.drag_surface_mut() {
+            let pos = surface.egui_state.to_points(x, y);
+            surface
+                .egui_state
+                .push_event(egui::Event::PointerMoved(pos));
+        }
+    }
+
+    // the clipboard is handled by smithay-clipboard on its own queue
+    fn selection(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _data_device: &WlDataDevice,
+    ) {
+    }
+
+    fn drop_performed(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _data_device: &WlDataDevice,
+    ) {
+        let Some(offer) = self
+            .data_device
+            .as_ref()
+            .and_then(|data_device| data_device.data().drag_offer())
+        else {
+            return;
+        };
+        let Some(id) = self.drag_surface else {
+            offer.destroy();
+            return;
+        };
+        self.clear_hovered_files();
+
+        let pipe = match offer.receive(URI_LIST_MIME.to_owned()) {
+            Ok(pipe) => pipe,
+            Err(err) => {
+                println!("could not receive dropped files: {err}");
+                offer.finish();
+                offer.destroy();
+                return;
+            }
+        };
+        // the source may take a while to write, so the pipe is read as data arrives instead
+        // of blocking the loop, which also flushes the receive request before it waits
+        let mut uri_list = Vec::new();
+        let inserted = self.loop_handle.insert_source(pipe, move |_, pipe, state| {
+            let mut buf = [0; 4096];
+            // a single read per wakeup never blocks, the pipe is readable
+            match pipe.as_ref().read(&mut buf) {
+                Ok(0) => {}
+                Ok(len) => {
+                    uri_list.extend_from_slice(&buf[..len]);
+                    return Ok(PostAction::Continue);
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => {
+                    return Ok(PostAction::Continue);
+                }
+                Err(err) => {
+                    println!("could not receive dropped files: {err}");
+                    uri_list.clear();
+                }
+            }
+
+            // the whole list arrived once the source closed its end
+            offer.finish();
+            offer.destroy();
+            let dropped_files = parse_uri_list(&uri_list);
+            if let Some(surface) = state.surface_mut(id) {
+                surface
+                    .egui_state
+                    .input()
+                    .dropped_files
+                    .extend(dropped_files);
+                surface.request_redraw();
+            }
+            Ok(PostAction::Remove)
+        });
+        if let Err(err) = inserted {
+            println!("could not receive dropped files: {}", err.error);
+        }
+    }
+}
+
+impl DataOfferHandler for WgpuLayerShellState {
+    fn source_actions(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        offer: &mut DragOffer,
+        _actions: DndAction,
+    ) {
+        offer.set_actions(DndAction::Copy, DndAction::Copy);
+    }
+
+    fn selected_action(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _offer: &mut DragOffer,
+        _actions: DndAction,
+    ) {
+    }
+}
+
+// no drags are started, so sources never need to be handled
+impl DataSourceHandler for WgpuLayerShellState {
+    fn accept_mime(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _source: &WlDataSource,
+        _mime: Option<String>,
+    ) {
+    }
+
+    fn send_request(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _source: &WlDataSource,
+        _mime: String,
+        _fd: WritePipe,
+    ) {
+    }
+
+    fn cancelled(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _source: &WlDataSource) {}
+
+    fn dnd_dropped(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _source: &WlDataSource) {
+    }
+
+    fn dnd_finished(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _source: &WlDataSource,
+    ) {
+    }
+
+    fn action(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _source: &WlDataSource,
+        _action: DndAction,
+    ) {
+    }
+}
+
+/// Parses a `text/uri-list`, keeping only local files.
+///
+/// The paths are kept as bytes, file names don't have to be valid UTF-8.
+fn parse_uri_list(uri_list: &[u8]) -> Vec<egui::DroppedFile> {
+    uri_list
+        .split(|byte| *byte == b'\n')
+        .map(<[u8]>::trim_ascii)
+        .filter(|line| !line.is_empty() && !line.starts_with(b"#"))
+        .filter_map(|uri| uri.strip_prefix(b"file://"))
+        // skip the host, it is either empty or `localhost`
+        .filter_map(|uri| {
+            uri.iter()
+                .position(|byte| *byte == b'/')
+                .map(|start| &uri[start..])
+        })
+        .map(|path| {
+            let path = PathBuf::from(OsString::from_vec(percent_decode(path)));
+            egui::DroppedFile {
+                name: path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                path: Some(path),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// Decodes `%XX` escapes, anything else that isn't two hex digits after a `%` is kept as is.
+fn percent_decode(text: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        match text[i..] {
+            [b'%', high, low, ..] if high.is_ascii_hexdigit() && low.is_ascii_hexdigit() => {
+                decoded.push((hex_value(high) << 4) | hex_value(low));
+                i += 3;
+            }
+            [byte, ..] => {
+                decoded.push(byte);
+                i += 1;
+            }
+            [] => unreachable!("i is in bounds"),
+        }
+    }
+    decoded
+}
+
+fn hex_value(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => digit - b'A' + 10,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt, path::Path};
+
+    use super::*;
+
+    fn paths(uri_list: &[u8]) -> Vec<PathBuf> {
+        parse_uri_list(uri_list)
+            .into_iter()
+            .filter_map(|file| file.path)
+            .collect()
+    }
+
+    #[test]
+    fn percent_escapes_are_decoded() {
+        assert_eq!(
+            paths(b"file:///home/user/My%20File.txt"),
+            [Path::new("/home/user/My File.txt")]
+        );
+        // names don't have to be UTF-8
+        assert_eq!(
+            paths(b"file:///tmp/%FF.bin"),
+            [Path::new(OsStr::from_bytes(b"/tmp/\xff.bin"))]
+        );
+        // only two hex digits make an escape
+        assert_eq!(percent_decode(b"%+5%2g%4"), b"%+5%2g%4");
+        assert_eq!(percent_decode(b"%2f%2F"), b"//");
+    }
+
+    #[test]
+    fn localhost_is_skipped() {
+        assert_eq!(
+            paths(b"file://localhost/etc/hosts\r\nfile:///etc/fstab\r\n"),
+            [Path::new("/etc/hosts"), Path::new("/etc/fstab")]
+        );
+    }
+
+    #[test]
+    fn comments_and_other_uris_are_skipped() {
+        let uri_list =
+            b"# dragged from a browser\r\nhttps://example.com/a.png\r\n\r\nfile:///a.png";
+        let files = parse_uri_list(uri_list);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].path.as_deref(), Some(Path::new("/a.png")));
+        assert_eq!(files[0].name, "a.png");
+    }
+}
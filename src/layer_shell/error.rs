@@ -0,0 +1,57 @@
+use smithay_client_toolkit::{
+    reexports::calloop,
+    shm::{slot::CreateBufferError, CreatePoolError},
+};
+use wayland_client::{
+    globals::{BindError, GlobalError},
+    ConnectError, DispatchError,
+};
+
+/// Errors that can occur while setting up or drawing the layer shell surface.
+#[derive(Debug, thiserror::Error)]
+pub enum LayerShellError {
+    #[error("could not connect to the wayland compositor: {0}")]
+    Connection(#[from] ConnectError),
+    #[error("could not initialize the wayland registry: {0}")]
+    Registry(#[from] GlobalError),
+    #[error("could not dispatch wayland events: {0}")]
+    Dispatch(#[from] DispatchError),
+    /// The compositor went away, see [`crate::WgpuLayerShellApp::reconnect`].
+    #[error("lost the connection to the wayland compositor")]
+    ConnectionLost,
+    #[error("event loop error: {0}")]
+    EventLoop(#[from] calloop::Error),
+    #[error("wl_compositor is not available: {0}")]
+    Compositor(BindError),
+    #[error("layer shell is not available: {0}")]
+    LayerShell(BindError),
+    #[error("session lock is not available: {0}")]
+    SessionLock(GlobalError),
+    #[error("the session is already locked")]
+    AlreadyLocked,
+    /// Returned by [`crate::WgpuLayerShellApp::new_lock`], the app needs a lock surface.
+    #[error("there is no output to lock")]
+    NoOutput,
+    #[error("wl_shm is not available: {0}")]
+    Shm(BindError),
+    #[error("could not create the shm pool: {0}")]
+    Pool(#[from] CreatePoolError),
+    #[error("could not create a {width}x{height} buffer: {source}")]
+    Buffer {
+        width: i32,
+        height: i32,
+        source: CreateBufferError,
+    },
+    #[error("a {width}x{height} frame doesn't fit a buffer of {len} bytes")]
+    BufferSize {
+        width: usize,
+        height: usize,
+        len: usize,
+    },
+    /// Also returned by [`crate::Headless`], which has no anchors to stretch between.
+    #[error("a width of 0 requires anchoring to the left and right edge")]
+    ZeroWidth,
+    /// Also returned by [`crate::Headless`], which has no anchors to stretch between.
+    #[error("a height of 0 requires anchoring to the top and bottom edge")]
+    ZeroHeight,
+}
@@ -1,11 +1,12 @@
-use egui::{Modifiers, RawInput};
+use egui::Modifiers;
 use smithay_client_toolkit::{
     delegate_keyboard,
     seat::keyboard::{KeyEvent, KeyboardHandler, Keysym},
 };
 use wayland_client::{protocol::wl_surface, Connection, QueueHandle};
 
-use super::WgpuLayerShellState;
+use super::{compose::ComposeResult, WgpuLayerShellState};
+use crate::egui_state;
 
 delegate_keyboard!(WgpuLayerShellState);
 
@@ -15,15 +16,20 @@ impl KeyboardHandler for WgpuLayerShellState {
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
         _keyboard: &wayland_client::protocol::wl_keyboard::WlKeyboard,
-        _surface: &wl_surface::WlSurface,
+        surface: &wl_surface::WlSurface,
         _serial: u32,
         _raw: &[u32],
         _keysyms: &[smithay_client_toolkit::seat::keyboard::Keysym],
     ) {
-        let input = self.egui_state.input();
+        let Some(surface) = self.surface_by_wl_mut(surface) else {
+            return;
+        };
+        let id = surface.id;
+        let input = surface.egui_state.input();
         input.focused = true;
         // todo: this should probably be in surface enter?
         input.events.push(egui::Event::WindowFocused(true));
+        self.keyboard_focus = Some(id);
     }
 
     fn leave(
@@ -31,13 +37,31 @@ impl KeyboardHandler for WgpuLayerShellState {
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
         _keyboard: &wayland_client::protocol::wl_keyboard::WlKeyboard,
-        _surface: &wl_surface::WlSurface,
+        surface: &wl_surface::WlSurface,
         _serial: u32,
     ) {
-        let input = self.egui_state.input();
+        if let Some(compose) = &mut self.compose {
+            compose.reset();
+        }
+        // the releases of held keys go to the newly focused client
+        self.shortcut_keys.clear();
+        let Some(surface) = self.surface_by_wl_mut(surface) else {
+            return;
+        };
+        let id = surface.id;
+        surface.egui_state.discard_unfocused_input();
+        // releases go to the newly focused surface, so held keys would stay down forever
+        release_held_keys(&mut surface.egui_state);
+        let input = surface.egui_state.input();
         input.focused = false;
+        // the compositor only sends modifiers to the focused surface,
+        // so they would otherwise stay pressed after the focus is lost
+        input.modifiers = Modifiers::NONE;
         // todo: this should probably be in surface enter?
         input.events.push(egui::Event::WindowFocused(false));
+        if self.keyboard_focus == Some(id) {
+            self.keyboard_focus = None;
+        }
     }
 
     fn press_key(
@@ -46,9 +70,24 @@ impl KeyboardHandler for WgpuLayerShellState {
         _qh: &QueueHandle<Self>,
         _keyboard: &wayland_client::protocol::wl_keyboard::WlKeyboard,
         _serial: u32,
-        event: smithay_client_toolkit::seat::keyboard::KeyEvent,
+        mut event: smithay_client_toolkit::seat::keyboard::KeyEvent,
     ) {
-        handle_key_press(event, true, self.egui_state.input());
+        if self.dispatch_shortcut(event.keysym, event.raw_code) {
+            return;
+        }
+        // dead keys and compose sequences replace the text of the keys they consist of
+        match self
+            .compose
+            .as_mut()
+            .map(|compose| compose.feed(event.keysym))
+        {
+            Some(ComposeResult::Pending) => event.utf8 = None,
+            Some(ComposeResult::Composed(text)) => event.utf8 = Some(text),
+            Some(ComposeResult::Pass) | None => {}
+        }
+        if let Some(surface) = self.keyboard_focus_mut() {
+            handle_key_press(event, KeyState::Pressed, &mut surface.egui_state);
+        }
     }
 
     fn release_key(
@@ -59,85 +98,163 @@ impl KeyboardHandler for WgpuLayerShellState {
         _serial: u32,
         event: smithay_client_toolkit::seat::keyboard::KeyEvent,
     ) {
-        handle_key_press(event, false, self.egui_state.input());
+        if self.shortcut_keys.release(event.raw_code) {
+            return;
+        }
+        if let Some(surface) = self.keyboard_focus_mut() {
+            handle_key_press(event, KeyState::Released, &mut surface.egui_state);
+        }
     }
 
     fn repeat_key(
         &mut self,
-        conn: &Connection,
-        qh: &QueueHandle<Self>,
-        keyboard: &wayland_client::protocol::wl_keyboard::WlKeyboard,
-        serial: u32,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _keyboard: &wayland_client::protocol::wl_keyboard::WlKeyboard,
+        _serial: u32,
         event: KeyEvent,
     ) {
-        todo!()
+        self.repeat_key_press(event);
     }
 
     fn update_modifiers(
         &mut self,
-        conn: &Connection,
-        qh: &QueueHandle<Self>,
-        keyboard: &wayland_client::protocol::wl_keyboard::WlKeyboard,
-        serial: u32,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _keyboard: &wayland_client::protocol::wl_keyboard::WlKeyboard,
+        _serial: u32,
         modifiers: smithay_client_toolkit::seat::keyboard::Modifiers,
-        raw_modifiers: smithay_client_toolkit::seat::keyboard::RawModifiers,
-        layout: u32,
+        _raw_modifiers: smithay_client_toolkit::seat::keyboard::RawModifiers,
+        _layout: u32,
     ) {
-        self.egui_state.input().modifiers = Modifiers {
-            alt: modifiers.alt,
-            ctrl: modifiers.ctrl,
-            shift: modifiers.shift,
-            mac_cmd: false, // this is linux only
-            command: modifiers.ctrl,
-        };
+        self.modifiers = modifiers;
+        let modifiers = translate_modifiers(modifiers);
+        // modifiers belong to the seat, so every surface sees the same state
+        for surface in &mut self.surfaces {
+            surface.egui_state.input().modifiers = modifiers;
+        }
+    }
+}
+
+fn translate_modifiers(modifiers: smithay_client_toolkit::seat::keyboard::Modifiers) -> Modifiers {
+    Modifiers {
+        alt: modifiers.alt,
+        ctrl: modifiers.ctrl,
+        shift: modifiers.shift,
+        mac_cmd: false, // this is linux only
+        // egui shortcuts use `command`, which is ctrl everywhere but on mac
+        command: modifiers.ctrl,
     }
 }
 
 fn handle_clipboard_shortcuts(
     key: egui::Key,
     modifiers: Modifiers,
-    egui_input: &mut RawInput,
+    egui_state: &mut egui_state::State,
 ) -> bool {
     let event = match (key, modifiers.ctrl) {
-        (egui::Key::C, true) => Some(egui::Event::Copy),
-        (egui::Key::X, true) => Some(egui::Event::Cut),
-        (egui::Key::V, true) => {
-            // todo: implement paste
-            None
+        (egui::Key::C, true) | (egui::Key::Copy, _) => Some(egui::Event::Copy),
+        (egui::Key::X, true) | (egui::Key::Cut, _) => Some(egui::Event::Cut),
+        (egui::Key::V, true) | (egui::Key::Paste, _) => {
+            egui_state.clipboard().load().map(egui::Event::Paste)
         }
         _ => None,
     };
 
     if let Some(event) = event {
-        egui_input.events.push(event);
+        egui_state.push_event(event);
         return true;
     }
 
     false
 }
 
-pub fn handle_key_press(event: KeyEvent, pressed: bool, egui_input: &mut RawInput) {
+fn release_held_keys(egui_state: &mut egui_state::State) {
+    let keys_down = egui_state.context().input(|input| input.keys_down.clone());
+    let modifiers = egui_state.modifiers();
+    for key in keys_down {
+        egui_state.push_event(egui::Event::Key {
+            key,
+            physical_key: None,
+            pressed: false,
+            repeat: false,
+            modifiers,
+        });
+    }
+}
+
+impl WgpuLayerShellState {
+    /// Forwards a repetition of the held key to the focused surface.
+    ///
+    /// Repeats are timed by the event loop and only queue input like any other key event,
+    /// the next frame picks them up. Keys of shortcuts don't repeat, their press never
+    /// reached egui.
+    pub(crate) fn repeat_key_press(&mut self, event: KeyEvent) {
+        if self.shortcut_keys.contains(event.raw_code) {
+            return;
+        }
+        if let Some(surface) = self.keyboard_focus_mut() {
+            handle_key_press(event, KeyState::Repeated, &mut surface.egui_state);
+        }
+    }
+}
+
+/// What happened to a key, repeats are sent while it is held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum KeyState {
+    Pressed,
+    Repeated,
+    Released,
+}
+
+pub(crate) fn handle_key_press(
+    event: KeyEvent,
+    state: KeyState,
+    egui_state: &mut egui_state::State,
+) {
+    egui_state.set_event_time(event.time);
+    let modifiers = egui_state.modifiers();
+
     if let Some(key) = keysym_to_egui_key(event.keysym) {
-        if pressed && handle_clipboard_shortcuts(key, egui_input.modifiers, egui_input) {
+        if state != KeyState::Released && handle_clipboard_shortcuts(key, modifiers, egui_state) {
             return;
         }
+    }
 
-        let key_event = egui::Event::Key {
+    for egui_event in key_events(event.keysym, event.utf8, state, modifiers) {
+        egui_state.push_event(egui_event);
+    }
+}
+
+/// Translates a key event to the events egui gets for it, clipboard shortcuts aside.
+fn key_events(
+    keysym: Keysym,
+    utf8: Option<String>,
+    state: KeyState,
+    modifiers: Modifiers,
+) -> Vec<egui::Event> {
+    let pressed = state != KeyState::Released;
+    let mut events = Vec::new();
+
+    if let Some(key) = keysym_to_egui_key(keysym) {
+        events.push(egui::Event::Key {
             physical_key: None,
-            repeat: false, // seems to be just handled by egui
+            repeat: state == KeyState::Repeated,
             key,
             pressed,
-            modifiers: egui_input.modifiers,
-        };
-
-        egui_input.events.push(key_event);
+            modifiers,
+        });
     }
 
-    if let Some(utf8_string) = event.utf8 {
+    // sctk rebuilds its xkb state when the compositor sends a new keymap or switches the
+    // layout, so `utf8` always follows the active layout. Only presses and repeats type text.
+    if let Some(utf8_string) = utf8.filter(|_| pressed) {
         if utf8_string.chars().all(is_printable_char) {
-            egui_input.events.push(egui::Event::Text(utf8_string));
+            events.push(egui::Event::Text(utf8_string));
         }
     }
+
+    events
 }
 
 fn keysym_to_egui_key(keysym: Keysym) -> Option<egui::Key> {
@@ -267,3 +384,102 @@ fn is_printable_char(chr: char) -> bool {
 
     !is_in_private_use_area && !chr.is_ascii_control()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_a(state: KeyState) -> Vec<egui::Event> {
+        key_events(Keysym::a, Some("a".to_owned()), state, Modifiers::NONE)
+    }
+
+    #[test]
+    fn held_key_repeats() {
+        let events: Vec<_> = [
+            KeyState::Pressed,
+            KeyState::Repeated,
+            KeyState::Repeated,
+            KeyState::Released,
+        ]
+        .into_iter()
+        .flat_map(key_a)
+        .collect();
+
+        let keys: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                egui::Event::Key {
+                    key: egui::Key::A,
+                    pressed,
+                    repeat,
+                    ..
+                } => Some((*pressed, *repeat)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            keys,
+            [(true, false), (true, true), (true, true), (false, false)]
+        );
+
+        let texts = events
+            .iter()
+            .filter(|event| matches!(event, egui::Event::Text(_)))
+            .count();
+        assert_eq!(texts, 3);
+    }
+
+    #[test]
+    fn release_types_no_text() {
+        assert!(!key_a(KeyState::Released)
+            .iter()
+            .any(|event| matches!(event, egui::Event::Text(_))));
+    }
+
+    /// Text typed by the key with the evdev `code` on `layout`, `None` without xkb data.
+    fn typed_text(layout: &str, code: u32) -> Option<Vec<String>> {
+        use xkbcommon::xkb;
+
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let keymap = xkb::Keymap::new_from_names(
+            &context,
+            "",
+            "pc105",
+            layout,
+            "",
+            None,
+            xkb::KEYMAP_COMPILE_NO_FLAGS,
+        )?;
+        let state = xkb::State::new(&keymap);
+        // xkb keycodes are the evdev ones offset by 8
+        let keycode = xkb::Keycode::new(code + 8);
+        let events = key_events(
+            state.key_get_one_sym(keycode),
+            Some(state.key_get_utf8(keycode)),
+            KeyState::Pressed,
+            Modifiers::NONE,
+        );
+        Some(
+            events
+                .into_iter()
+                .filter_map(|event| match event {
+                    egui::Event::Text(text) => Some(text),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn text_follows_the_layout() {
+        // the key right of L
+        const SEMICOLON: u32 = 39;
+        let (Some(de), Some(us)) = (typed_text("de", SEMICOLON), typed_text("us", SEMICOLON))
+        else {
+            println!("skipping, xkeyboard-config is not installed");
+            return;
+        };
+        assert_eq!(de, ["ö"]);
+        assert_eq!(us, [";"]);
+    }
+}
@@ -1,53 +1,83 @@
+mod compose;
+mod cursor;
+mod damage;
+mod data_device;
+mod error;
 mod keyboard_handler;
+mod options;
+mod output;
+mod per_output;
 mod pointer_handler;
+mod scale;
+mod session_lock;
+mod shortcuts;
+mod surface;
+mod text_input;
+mod touch_handler;
+
+pub use error::LayerShellError;
+pub use options::{
+    BufferFormat, Clock, ExclusiveZone, FirstDraw, LayerShellOptions, LayerShellOptionsBuilder,
+    OutputSelector, RendererOptions, DEFAULT_NAMESPACE,
+};
+pub use output::SurfaceOutput;
+pub use shortcuts::Shortcut;
+pub use surface::{LayerShellSurface, SurfaceId};
 
 use std::{
+    collections::HashMap,
     sync::{Arc, RwLock},
     time::{Duration, Instant},
 };
 
-use egui_software_backend::BufferMutRef;
-use keyboard_handler::handle_key_press;
+use compose::Compose;
+use per_output::PerOutput;
+use session_lock::ActiveLock;
+use shortcuts::{ShortcutAction, ShortcutKeys};
 use smithay_client_toolkit::{
     compositor::{CompositorHandler, CompositorState},
+    data_device_manager::{data_device::DataDevice, DataDeviceManagerState},
     delegate_compositor, delegate_layer, delegate_output, delegate_registry, delegate_seat,
     delegate_shm,
     output::{OutputHandler, OutputState},
-    reexports::{calloop::LoopHandle, calloop_wayland_source::WaylandSource},
+    reexports::{
+        calloop::{self, ping::make_ping, LoopHandle},
+        calloop_wayland_source::WaylandSource,
+    },
     registry::{ProvidesRegistryState, RegistryState},
     registry_handlers,
-    seat::{Capability, SeatHandler, SeatState},
+    seat::{
+        keyboard::{Modifiers, RepeatInfo},
+        pointer::{ThemeSpec, ThemedPointer},
+        Capability, SeatHandler, SeatState,
+    },
+    session_lock::SessionLockState,
     shell::{
-        wlr_layer::{
-            Anchor, KeyboardInteractivity, Layer, LayerShell, LayerShellHandler, LayerSurface,
-            LayerSurfaceConfigure,
-        },
+        wlr_layer::{Layer, LayerShell, LayerShellHandler, LayerSurface, LayerSurfaceConfigure},
         WaylandSurface,
     },
     shm::{slot::SlotPool, Shm, ShmHandler},
 };
+use surface::{LayerState, SurfaceRole};
+use text_input::TextInput;
 use wayland_client::{
-    globals::registry_queue_init,
-    protocol::{wl_keyboard::WlKeyboard, wl_output, wl_pointer::WlPointer, wl_seat, wl_surface},
+    globals::{registry_queue_init, BindError},
+    protocol::{wl_keyboard::WlKeyboard, wl_output, wl_seat, wl_surface, wl_touch::WlTouch},
     Connection, QueueHandle,
 };
+use wayland_protocols::wp::{
+    fractional_scale::v1::client::wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+    text_input::zv3::client::zwp_text_input_manager_v3::ZwpTextInputManagerV3,
+    viewporter::client::wp_viewporter::WpViewporter,
+};
 
 use crate::{
+    clipboard::Clipboard,
     egui_state::{self},
-    App,
+    fonts, theme, App, LayerShellHandle,
 };
 
-#[derive(Default)]
-pub struct LayerShellOptions {
-    pub layer: Option<Layer>,
-    pub namespace: String,
-    pub width: u32,
-    pub height: u32,
-    pub anchor: Option<Anchor>,
-    pub keyboard_interactivity: Option<KeyboardInteractivity>,
-}
-
-pub(crate) struct WgpuLayerShellState {
+pub struct WgpuLayerShellState {
     //event_loop: Arc<EventLoop<'static, Self>>,
     loop_handle: LoopHandle<'static, Self>,
     registry_state: RegistryState,
@@ -55,168 +85,508 @@ pub(crate) struct WgpuLayerShellState {
     output_state: OutputState,
     pub(crate) queue_handle: Arc<QueueHandle<Self>>,
 
-    pub(crate) layer: LayerSurface,
-    pointer: Option<WlPointer>,
+    compositor_state: CompositorState,
+    // `None` for a lock screen on a compositor without it
+    layer_shell: Option<LayerShell>,
+    // only set if the compositor supports fractional scaling
+    fractional_scale_manager: Option<(WpFractionalScaleManagerV1, WpViewporter)>,
+    text_input_manager: Option<ZwpTextInputManagerV3>,
+    data_device_manager: Option<DataDeviceManagerState>,
+    session_lock_state: SessionLockState,
+    session_lock: Option<ActiveLock>,
+
+    pub(crate) surfaces: Vec<LayerShellSurface>,
+    next_surface_id: u32,
+    per_output: Vec<PerOutput>,
+
+    pointer: Option<ThemedPointer>,
+    data_device: Option<DataDevice>,
+    // surface files are currently dragged over
+    drag_surface: Option<SurfaceId>,
+    pointer_focus: Option<SurfaceId>,
+    pointer_serial: Option<u32>,
+    // cursor currently shown, `None` if it has to be set again
+    cursor_icon: Option<egui::CursorIcon>,
     keyboard: Option<WlKeyboard>,
-
-    pub(crate) has_frame_callback: bool,
-    is_configured: bool,
-
-    pub(crate) exit: bool,
-
-    pub(crate) egui_state: egui_state::State,
-    pub(crate) draw_request: Arc<RwLock<Option<Instant>>>,
+    repeat_info: Option<RepeatInfo>,
+    // `None` if the locale has no compose table
+    compose: Option<Compose>,
+    // modifiers of the seat as of the last key event, for matching shortcuts
+    modifiers: Modifiers,
+    shortcuts: Vec<(Shortcut, ShortcutAction)>,
+    // held keys whose press ran a shortcut
+    shortcut_keys: ShortcutKeys,
+    keyboard_focus: Option<SurfaceId>,
+    text_input: Option<TextInput>,
+    touch: Option<WlTouch>,
+    // surface and last known position of every active touch point
+    touch_points: HashMap<i32, (SurfaceId, egui::Pos2)>,
+    // touch point that is emulating the pointer
+    primary_touch: Option<i32>,
+
+    pub(crate) handle: LayerShellHandle,
+    // system theme the surfaces were last redrawn for
+    system_theme: Option<egui::Theme>,
+    // created once the first surface with clipboard support is added
+    clipboard: Option<Clipboard>,
 
     pub(crate) shm: Shm,
     pub(crate) pool: SlotPool,
+
+    // declared last so it is dropped after the clipboard, which borrows the display
+    connection: Connection,
 }
 
 impl WgpuLayerShellState {
-    pub(crate) fn new(loop_handle: LoopHandle<'static, Self>, options: LayerShellOptions) -> Self {
-        let connection = Connection::connect_to_env().unwrap();
-        let (global_list, event_queue) = registry_queue_init(&connection).unwrap();
-        let queue_handle: Arc<QueueHandle<WgpuLayerShellState>> = Arc::new(event_queue.handle());
-
-        WaylandSource::new(connection.clone(), event_queue)
-            .insert(loop_handle.clone())
-            .unwrap();
+    pub(crate) fn new(
+        loop_handle: LoopHandle<'static, Self>,
+        options: LayerShellOptions,
+    ) -> Result<Self, LayerShellError> {
+        let mut state = Self::connect(loop_handle, options.pool_size(), options.repeat_info)?;
+        state.add_surface(options)?;
+        Ok(state)
+    }
 
-        let compositor_state = CompositorState::bind(&global_list, &queue_handle)
-            .expect("wl_compositor not available");
+    /// Connects without a layer surface and locks the session, see [`Self::lock`].
+    ///
+    /// The layer shell isn't required then. The shm pool starts at its minimum size and
+    /// grows with the outputs, keys repeat at the rate of the compositor.
+    pub(crate) fn new_lock(
+        loop_handle: LoopHandle<'static, Self>,
+        options: impl FnMut(&wl_output::WlOutput) -> LayerShellOptions + 'static,
+    ) -> Result<Self, LayerShellError> {
+        let mut state = Self::connect(loop_handle, LayerShellOptions::default().pool_size(), None)?;
+        state.lock(options)?;
+        Ok(state)
+    }
 
-        let wl_surface = compositor_state.create_surface(&queue_handle);
+    fn connect(
+        loop_handle: LoopHandle<'static, Self>,
+        pool_size: usize,
+        repeat_info: Option<RepeatInfo>,
+    ) -> Result<Self, LayerShellError> {
+        let connection = Connection::connect_to_env()?;
+        let (global_list, mut event_queue) = registry_queue_init(&connection)?;
+        let queue_handle: Arc<QueueHandle<WgpuLayerShellState>> = Arc::new(event_queue.handle());
 
-        let layer_shell =
-            LayerShell::bind(&global_list, &queue_handle).expect("layer shell not available");
-        let layer_surface = layer_shell.create_layer_surface(
-            &queue_handle,
-            wl_surface,
-            options.layer.unwrap_or(Layer::Top),
-            Some(options.namespace),
-            None,
-        );
-        if let Some(anchor) = options.anchor {
-            layer_surface.set_anchor(anchor);
-        }
-        if let Some(keyboard_interactivity) = options.keyboard_interactivity {
-            layer_surface.set_keyboard_interactivity(keyboard_interactivity);
-        }
-        layer_surface.set_size(options.width, options.height);
-        layer_surface.commit();
+        let compositor_state = CompositorState::bind(&global_list, &queue_handle)
+            .map_err(LayerShellError::Compositor)?;
 
-        let shm = Shm::bind(&global_list, &queue_handle).expect("wl_shm not available");
-        let pool = SlotPool::new(256 * 256 * 4, &shm).expect("Failed to create slot pool");
+        let fractional_scale_manager = global_list
+            .bind::<WpFractionalScaleManagerV1, _, _>(&queue_handle, 1..=1, ())
+            .ok();
+        let viewporter = global_list
+            .bind::<WpViewporter, _, _>(&queue_handle, 1..=1, ())
+            .ok();
+        let text_input_manager = global_list
+            .bind::<ZwpTextInputManagerV3, _, _>(&queue_handle, 1..=1, ())
+            .ok();
 
-        let egui_context = egui::Context::default();
+        let data_device_manager = DataDeviceManagerState::bind(&global_list, &queue_handle).ok();
 
-        let draw_request = Arc::new(RwLock::new(None));
+        let layer_shell = LayerShell::bind(&global_list, &queue_handle).ok();
 
-        egui_context.set_request_repaint_callback({
-            let draw_request = Arc::clone(&draw_request);
-            move |info| {
-                let mut draw_request = draw_request.write().unwrap();
-                *draw_request = Some(Instant::now() + info.delay);
-            }
-        });
+        let shm = Shm::bind(&global_list, &queue_handle).map_err(LayerShellError::Shm)?;
+        let pool = SlotPool::new(pool_size, &shm)?;
 
-        let egui_state = egui_state::State::new(egui_context);
+        let (ping, ping_source) = make_ping().map_err(calloop::Error::IoError)?;
+        loop_handle
+            .insert_source(ping_source, |_, _, state| state.apply_system_theme())
+            .map_err(|err| err.error)?;
 
-        WgpuLayerShellState {
+        let mut state = WgpuLayerShellState {
             loop_handle: loop_handle.clone(),
             registry_state: RegistryState::new(&global_list),
             seat_state: SeatState::new(&global_list, &queue_handle),
             output_state: OutputState::new(&global_list, &queue_handle),
 
-            exit: false,
-            layer: layer_surface,
+            compositor_state,
+            layer_shell,
+            fractional_scale_manager: fractional_scale_manager.zip(viewporter),
+            text_input_manager,
+            data_device_manager,
+            session_lock_state: SessionLockState::new(&global_list, &queue_handle),
+            session_lock: None,
+
+            surfaces: Vec::new(),
+            next_surface_id: 0,
+            per_output: Vec::new(),
 
             pointer: None,
+            data_device: None,
+            drag_surface: None,
+            pointer_focus: None,
+            pointer_serial: None,
+            cursor_icon: None,
             keyboard: None,
-
-            has_frame_callback: false,
-            is_configured: false,
+            repeat_info,
+            compose: Compose::new(),
+            modifiers: Modifiers::default(),
+            shortcuts: Vec::new(),
+            shortcut_keys: ShortcutKeys::default(),
+            keyboard_focus: None,
+            text_input: None,
+            touch: None,
+            touch_points: HashMap::new(),
+            primary_touch: None,
 
             queue_handle,
 
-            egui_state,
-            draw_request,
+            handle: LayerShellHandle::new(ping),
+            system_theme: None,
+            clipboard: None,
 
             shm,
             pool,
+
+            connection,
+        };
+
+        // receive the output information, so surfaces can select their output by name
+        event_queue.roundtrip(&mut state)?;
+        WaylandSource::new(state.connection.clone(), event_queue)
+            .insert(loop_handle)
+            .map_err(|err| err.error)?;
+
+        theme::watch(state.handle.clone());
+        Ok(state)
+    }
+
+    /// Serial of the latest pointer enter, press or release.
+    ///
+    /// Requests acting on behalf of the user, like setting the cursor or starting a grab,
+    /// have to pass it to the compositor.
+    pub fn pointer_serial(&self) -> Option<u32> {
+        self.pointer_serial
+    }
+
+    /// Whether the compositor is still reachable, flushing fails once the socket is closed.
+    pub(crate) fn is_connected(&self) -> bool {
+        self.connection.flush().is_ok()
+    }
+
+    /// Destroys all surfaces and input devices and waits for the compositor to process it.
+    ///
+    /// Unlocks the session if it is locked and requests the event loop to exit. The state
+    /// holds no surfaces afterwards, the shm pool and the connection are released when it is
+    /// dropped, e.g. by [`crate::WgpuLayerShellApp::reconnect`].
+    pub fn shutdown(&mut self) {
+        self.unlock();
+        self.per_output.clear();
+        // destroys the layer surfaces with their buffers
+        self.surfaces.clear();
+
+        if let Some(text_input) = self.text_input.take() {
+            text_input.destroy();
         }
+        if let Some(pointer) = self.pointer.take() {
+            pointer.pointer().release();
+        }
+        if let Some(keyboard) = self.keyboard.take() {
+            keyboard.release();
+        }
+        if let Some(touch) = self.touch.take() {
+            touch.release();
+        }
+        self.data_device = None;
+        self.clipboard = None;
+        self.pointer_focus = None;
+        self.keyboard_focus = None;
+        self.drag_surface = None;
+        self.touch_points.clear();
+        self.primary_touch = None;
+
+        if let Err(err) = self.connection.roundtrip() {
+            println!("could not sync the shutdown with the compositor: {err}");
+        }
+        self.handle.exit();
     }
 
-    //fn request_redraw(&self, )
+    /// Creates another layer surface driven by the same connection and event loop.
+    ///
+    /// The app's [`App::update_surface`] is called with the returned id for each of its frames.
+    /// Fails if the compositor doesn't support the layer shell, which only a lock screen can
+    /// do without.
+    pub fn add_surface(
+        &mut self,
+        options: LayerShellOptions,
+    ) -> Result<SurfaceId, LayerShellError> {
+        let Some(layer_shell) = self.layer_shell.take() else {
+            return Err(LayerShellError::LayerShell(BindError::NotPresent));
+        };
+        let (id, wl_surface) = self.new_surface_id();
+
+        let output = options
+            .output
+            .as_ref()
+            .and_then(|selector| self.find_output(selector));
+        let layer_surface = layer_shell.create_layer_surface(
+            &self.queue_handle,
+            wl_surface,
+            options.layer.unwrap_or(Layer::Top),
+            Some(options.namespace()),
+            output.as_ref(),
+        );
+        self.layer_shell = Some(layer_shell);
+        LayerState::new(&options).send(&layer_surface);
+        layer_surface.commit();
 
-    pub(crate) fn should_draw(&mut self) -> bool {
-        if !self.has_frame_callback {
-            return false;
+        self.push_surface(id, SurfaceRole::Layer(layer_surface), options);
+        Ok(id)
+    }
+
+    fn new_surface_id(&mut self) -> (SurfaceId, wl_surface::WlSurface) {
+        let id = SurfaceId(self.next_surface_id);
+        self.next_surface_id += 1;
+        (id, self.compositor_state.create_surface(&self.queue_handle))
+    }
+
+    /// Sets up egui for a surface whose role object was just created.
+    fn push_surface(&mut self, id: SurfaceId, role: SurfaceRole, mut options: LayerShellOptions) {
+        // only argb8888 and xrgb8888 have to be supported by every compositor
+        if !self.shm.formats().contains(&options.format.shm_format()) {
+            println!(
+                "buffer format {:?} is not supported, falling back to Argb8888",
+                options.format
+            );
+            options.format = BufferFormat::Argb8888;
         }
 
-        if !self.egui_state.input().events.is_empty() {
-            return true;
+        let (fractional_scale, viewport) = match &self.fractional_scale_manager {
+            Some((fractional_scale_manager, viewporter)) => (
+                Some(fractional_scale_manager.get_fractional_scale(
+                    role.wl_surface(),
+                    &self.queue_handle,
+                    id,
+                )),
+                Some(viewporter.get_viewport(role.wl_surface(), &self.queue_handle, ())),
+            ),
+            None => (None, None),
+        };
+
+        let egui_context = options.context.take().unwrap_or_default();
+        if options.reduced_motion {
+            egui_context.all_styles_mut(|style| style.animation_time = 0.0);
         }
+        egui_context.set_theme(options.theme);
+        fonts::install(&egui_context, &options.fonts);
+
+        let draw_request = Arc::new(RwLock::new(None));
+
+        egui_context.set_request_repaint_callback({
+            let draw_request = Arc::clone(&draw_request);
+            let handle = self.handle.clone();
+            move |info| {
+                *draw_request.write().unwrap() = Some(Instant::now() + info.delay);
+                // repaints can be requested from other threads while the loop is waiting
+                handle.wake();
+            }
+        });
 
-        match *self.draw_request.read().unwrap() {
-            Some(time) => time <= Instant::now(),
-            None => false,
+        let clipboard = if options.clipboard {
+            self.clipboard
+                .get_or_insert_with(|| {
+                    // SAFETY: the connection is stored in the state and outlives the clipboard
+                    unsafe { Clipboard::new(self.connection.backend().display_ptr().cast()) }
+                })
+                .clone()
+        } else {
+            Clipboard::disabled()
+        };
+
+        self.handle.install(&egui_context);
+        self.handle.add_draw_request(&draw_request);
+
+        let mut egui_state = egui_state::State::new(
+            egui_context,
+            clipboard,
+            options.format.color_order(),
+            options.renderer,
+        );
+        if let Some(clock) = options.clock.take() {
+            egui_state.set_clock(clock);
+        }
+        if let Some(pixels_per_point) = options.scale_override {
+            egui_state.set_scale_override(pixels_per_point);
+        }
+        if !options.unfocused_input {
+            egui_state.set_unfocused_input(false);
         }
+        if let Some(url_opener) = options.url_opener.take() {
+            egui_state.set_url_opener(url_opener);
+        }
+
+        self.surfaces.push(LayerShellSurface::new(
+            id,
+            role,
+            fractional_scale,
+            viewport,
+            egui_state,
+            draw_request,
+            &options,
+        ));
     }
 
-    pub(crate) fn get_timeout(&self) -> Option<Duration> {
-        match *self.draw_request.read().unwrap() {
-            Some(instant) => {
-                if self.has_frame_callback {
-                    Some(instant.duration_since(Instant::now()))
-                } else {
-                    None
+    fn find_output(&self, selector: &OutputSelector) -> Option<wl_output::WlOutput> {
+        match selector {
+            OutputSelector::Output(output) => Some(output.clone()),
+            OutputSelector::Name(name) => {
+                let output = self.output_state.outputs().find(|output| {
+                    self.output_state
+                        .info(output)
+                        .is_some_and(|info| info.name.as_ref() == Some(name))
+                });
+                if output.is_none() {
+                    println!("output {name} not found, letting the compositor choose");
                 }
+                output
             }
-            None => None,
         }
     }
 
-    pub(crate) fn draw(&mut self, application: &mut dyn App) {
-        *self.draw_request.write().unwrap() = None;
-        self.has_frame_callback = false;
+    pub fn surface(&self, id: SurfaceId) -> Option<&LayerShellSurface> {
+        self.surfaces.iter().find(|surface| surface.id == id)
+    }
 
-        let full_output = self
-            .egui_state
-            .process_events(|ctx| application.update(ctx));
+    pub fn surface_mut(&mut self, id: SurfaceId) -> Option<&mut LayerShellSurface> {
+        self.surfaces.iter_mut().find(|surface| surface.id == id)
+    }
+
+    pub(crate) fn surface_by_wl_mut(
+        &mut self,
+        wl_surface: &wl_surface::WlSurface,
+    ) -> Option<&mut LayerShellSurface> {
+        self.surfaces
+            .iter_mut()
+            .find(|surface| surface.wl_surface() == wl_surface)
+    }
 
-        let (w, h) = self.egui_state.get_size();
-        let (buffer, canvas) = self
-            .pool
-            .create_buffer(
-                w,
-                h,
-                w * 4,
-                wayland_client::protocol::wl_shm::Format::Argb8888,
-            )
-            .unwrap();
-        buffer
-            .attach_to(self.layer.wl_surface())
-            .expect("buffer attach");
+    pub(crate) fn keyboard_focus_mut(&mut self) -> Option<&mut LayerShellSurface> {
+        let id = self.keyboard_focus?;
+        self.surface_mut(id)
+    }
+
+    //fn request_redraw(&self, )
 
-        // clear old buffer*
-        canvas.fill(0);
+    /// Applies the largest integer scale of the outputs the surface is on.
+    ///
+    /// Surfaces with fractional scaling get their scale from the compositor instead.
+    fn update_output_scale(&mut self, id: SurfaceId) {
+        let output_state = &self.output_state;
+        let Some(surface) = self.surfaces.iter_mut().find(|surface| surface.id == id) else {
+            return;
+        };
+        if surface.fractional_scale.is_some() {
+            return;
+        }
+        let Some(scale) = surface
+            .outputs
+            .iter()
+            .filter_map(|output| output_state.info(output))
+            .map(|info| info.scale_factor)
+            .max()
+        else {
+            return;
+        };
+        surface.set_scale(scale as f32);
+    }
 
-        let buffer_ref =
-            &mut BufferMutRef::new(bytemuck::cast_slice_mut(canvas), w as usize, h as usize);
+    /// Largest logical width and height of the connected outputs, `None` before any output
+    /// is known.
+    fn largest_output_size(&self) -> Option<(u32, u32)> {
+        self.output_state
+            .outputs()
+            .filter_map(|output| self.output_state.info(&output)?.logical_size)
+            .map(|(width, height)| (width.max(0) as u32, height.max(0) as u32))
+            .reduce(|(max_width, max_height), (width, height)| {
+                (max_width.max(width), max_height.max(height))
+            })
+    }
 
-        self.egui_state.draw(full_output, buffer_ref);
+    pub(crate) fn should_draw(&mut self) -> bool {
+        self.surfaces
+            .iter()
+            .any(|surface| surface.should_draw(&mut self.pool))
+    }
 
-        // attach content
-        self.layer.wl_surface().damage_buffer(0, 0, w, h);
+    pub(crate) fn get_timeout(&mut self) -> Option<Duration> {
+        self.surfaces
+            .iter()
+            .filter_map(|surface| surface.get_timeout(&mut self.pool))
+            .min()
+    }
 
-        // set size
-        self.layer.set_size(w as u32, h as u32);
+    pub(crate) fn draw(&mut self, application: &mut dyn App) {
+        self.draw_surfaces(application, false);
+    }
 
-        self.layer
-            .wl_surface()
-            .frame(&self.queue_handle, self.layer.wl_surface().clone());
+    /// Draws a frame on every shown surface right away, even if no redraw was requested or
+    /// the compositor didn't ask for a frame yet.
+    ///
+    /// Surfaces whose buffers are all still held by the compositor are skipped with a logged
+    /// message, nothing is allocated for them.
+    ///
+    /// Meant for tests and screenshot flows, [`crate::Headless`] renders without a compositor.
+    pub fn force_draw(&mut self, application: &mut dyn App) {
+        self.draw_surfaces(application, true);
+    }
 
-        self.layer.wl_surface().commit();
+    /// Redraws the surfaces after [`LayerShellHandle::set_system_theme`] changed the theme.
+    fn apply_system_theme(&mut self) {
+        let system_theme = self.handle.system_theme();
+        if self.system_theme == system_theme {
+            return;
+        }
+        self.system_theme = system_theme;
+        for surface in &self.surfaces {
+            surface.request_redraw();
+        }
+    }
+
+    fn draw_surfaces(&mut self, application: &mut dyn App, force: bool) {
+        let mut drawn = Vec::new();
+        let animating = self.handle.is_animating();
+        let system_theme = self.handle.system_theme();
+        for surface in &mut self.surfaces {
+            let due = if force {
+                // forcing skips the frame pacing, not the buffers the compositor still reads
+                let can_draw = surface.can_force_draw(&mut self.pool);
+                if !can_draw && surface.is_mapped() {
+                    println!(
+                        "not forcing a frame on {:?}: both buffers are busy",
+                        surface.id
+                    );
+                }
+                can_draw
+            } else {
+                surface.should_draw(&mut self.pool)
+            };
+            if !due {
+                continue;
+            }
+            surface.egui_state.input().system_theme = system_theme;
+            // a failed frame keeps showing the previous one, the next request retries
+            match surface.draw(
+                &mut self.pool,
+                &self.compositor_state,
+                &self.queue_handle,
+                application,
+            ) {
+                Ok(()) => {
+                    // the request is drawn once the next frame callback arrives
+                    if animating || surface.continuous {
+                        surface.request_redraw();
+                    }
+                    drawn.push(surface.id);
+                }
+                Err(err) => println!("skipping frame: {err}"),
+            }
+        }
+
+        for id in drawn {
+            self.update_text_input(id);
+        }
+        self.update_cursor();
     }
 }
 
@@ -238,24 +608,43 @@ impl OutputHandler for WgpuLayerShellState {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
+        output: wl_output::WlOutput,
     ) {
+        self.add_replicas(&output);
+        // a locked session has to stay covered
+        self.lock_output(&output);
     }
 
     fn update_output(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
+        output: wl_output::WlOutput,
     ) {
+        // the scale, mode or position of the output may have changed
+        let ids: Vec<_> = self
+            .surfaces
+            .iter()
+            .filter(|surface| surface.outputs.contains(&output))
+            .map(|surface| surface.id)
+            .collect();
+        for id in ids {
+            self.update_output_scale(id);
+            self.update_output_info(id);
+        }
     }
 
     fn output_destroyed(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _output: wl_output::WlOutput,
+        output: wl_output::WlOutput,
     ) {
+        self.remove_replicas(&output);
+        self.unlock_output(&output);
+        for surface in &mut self.surfaces {
+            surface.outputs.retain(|entered| entered != &output);
+        }
     }
 }
 
@@ -265,9 +654,17 @@ impl CompositorHandler for WgpuLayerShellState {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
-        _new_factor: i32,
+        surface: &wl_surface::WlSurface,
+        new_factor: i32,
     ) {
+        let Some(surface) = self.surface_by_wl_mut(surface) else {
+            return;
+        };
+        // the preferred fractional scale takes precedence over the integer one
+        if surface.fractional_scale.is_some() {
+            return;
+        }
+        surface.set_scale(new_factor as f32);
     }
 
     fn transform_changed(
@@ -283,42 +680,65 @@ impl CompositorHandler for WgpuLayerShellState {
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
-        _time: u32,
+        surface: &wl_surface::WlSurface,
+        time: u32,
     ) {
-        self.has_frame_callback = true;
+        if let Some(surface) = self.surface_by_wl_mut(surface) {
+            surface.has_frame_callback = true;
+            surface.frame_time = Some(time);
+        }
     }
 
     fn surface_enter(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
-        _output: &wl_output::WlOutput,
+        surface: &wl_surface::WlSurface,
+        output: &wl_output::WlOutput,
     ) {
+        let Some(surface) = self.surface_by_wl_mut(surface) else {
+            return;
+        };
+        if !surface.outputs.contains(output) {
+            surface.outputs.push(output.clone());
+        }
+        let id = surface.id;
+        self.update_output_scale(id);
+        self.update_output_info(id);
     }
 
     fn surface_leave(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _surface: &wl_surface::WlSurface,
-        _output: &wl_output::WlOutput,
+        surface: &wl_surface::WlSurface,
+        output: &wl_output::WlOutput,
     ) {
+        let Some(surface) = self.surface_by_wl_mut(surface) else {
+            return;
+        };
+        surface.outputs.retain(|entered| entered != output);
+        let id = surface.id;
+        self.update_output_scale(id);
+        self.update_output_info(id);
     }
 }
 
 delegate_layer!(WgpuLayerShellState);
 impl LayerShellHandler for WgpuLayerShellState {
-    fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _layer: &LayerSurface) {
-        self.exit = true;
+    fn closed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, layer: &LayerSurface) {
+        self.surfaces
+            .retain(|surface| surface.wl_surface() != layer.wl_surface());
+        if self.surfaces.is_empty() {
+            self.handle.exit();
+        }
     }
 
     fn configure(
         &mut self,
         _conn: &Connection,
         _qh: &QueueHandle<Self>,
-        _layer: &LayerSurface,
+        layer: &LayerSurface,
         configure: LayerSurfaceConfigure,
         _serial: u32,
     ) {
@@ -326,15 +746,54 @@ impl LayerShellHandler for WgpuLayerShellState {
             "LayerShell configure: size=({}, {})",
             configure.new_size.0, configure.new_size.1
         );
-        if !self.is_configured {
-            self.is_configured = true;
-            self.has_frame_callback = true;
-            *self.draw_request.write().unwrap() = Some(Instant::now());
+        let largest_output = self.largest_output_size();
+        let Some(surface) = self.surface_by_wl_mut(layer.wl_surface()) else {
+            return;
+        };
+        let Some((width, height)) = configured_size(
+            configure.new_size,
+            surface.layer_state.size,
+            surface.max_size.or(largest_output),
+        ) else {
+            println!(
+                "LayerShell configure: no size for {:?}, ignoring",
+                configure.new_size
+            );
+            return;
+        };
+        if width < configure.new_size.0 || height < configure.new_size.1 {
+            println!("LayerShell configure: clamped to {width}x{height}");
         }
 
-        self.egui_state
-            .set_size(configure.new_size.0, configure.new_size.1);
+        // the configured size already has the margins subtracted, so it can be used as is
+        surface.configure(width, height);
+    }
+}
+
+/// Resolves the size of a layer surface configure, `None` leaves the surface as it is.
+///
+/// The compositor sends 0 for dimensions it leaves to the client, those keep the requested
+/// size. Sizes beyond `max_size` are clamped to it.
+fn configured_size(
+    configured: (u32, u32),
+    requested: (u32, u32),
+    max_size: Option<(u32, u32)>,
+) -> Option<(u32, u32)> {
+    let width = match configured.0 {
+        0 => requested.0,
+        width => width,
+    };
+    let height = match configured.1 {
+        0 => requested.1,
+        height => height,
+    };
+    if width == 0 || height == 0 {
+        return None;
     }
+    Some(match max_size {
+        Some((max_width, max_height)) => (width.min(max_width), height.min(max_height)),
+        None => (width, height),
+    })
 }
 
 delegate_seat!(WgpuLayerShellState);
@@ -343,7 +802,13 @@ impl SeatHandler for WgpuLayerShellState {
         &mut self.seat_state
     }
 
-    fn new_seat(&mut self, _: &Connection, _: &QueueHandle<Self>, _: wl_seat::WlSeat) {}
+    fn new_seat(&mut self, _: &Connection, qh: &QueueHandle<Self>, seat: wl_seat::WlSeat) {
+        if let Some(data_device_manager) = &self.data_device_manager {
+            if self.data_device.is_none() {
+                self.data_device = Some(data_device_manager.get_data_device(qh, &seat));
+            }
+        }
+    }
 
     fn new_capability(
         &mut self,
@@ -354,9 +819,16 @@ impl SeatHandler for WgpuLayerShellState {
     ) {
         match capability {
             Capability::Pointer if self.pointer.is_none() => {
+                let cursor_surface = self.compositor_state.create_surface(qh);
                 let pointer = self
                     .seat_state
-                    .get_pointer(qh, &seat)
+                    .get_pointer_with_theme(
+                        qh,
+                        &seat,
+                        self.shm.wl_shm(),
+                        cursor_surface,
+                        ThemeSpec::System,
+                    )
                     .expect("Failed to create pointer");
                 self.pointer = Some(pointer);
             }
@@ -366,14 +838,26 @@ impl SeatHandler for WgpuLayerShellState {
                         .get_keyboard_with_repeat(
                             qh,
                             &seat,
-                            None,
+                            self.repeat_info,
                             self.loop_handle.clone(),
-                            Box::new(|state, _wl_kbd, event| {
-                                handle_key_press(event, true, &mut state.egui_state.input());
-                            }),
+                            Box::new(|state, _wl_kbd, event| state.repeat_key_press(event)),
                         )
                         .expect("Failed to create keyboard"),
                 );
+                if let Some(text_input_manager) = &self.text_input_manager {
+                    self.text_input = Some(TextInput::new(text_input_manager.get_text_input(
+                        &seat,
+                        qh,
+                        (),
+                    )));
+                }
+            }
+            Capability::Touch if self.touch.is_none() => {
+                let touch = self
+                    .seat_state
+                    .get_touch(qh, &seat)
+                    .expect("Failed to create touch");
+                self.touch = Some(touch);
             }
             _ => {}
         }
@@ -388,16 +872,25 @@ impl SeatHandler for WgpuLayerShellState {
     ) {
         match capability {
             Capability::Pointer if self.pointer.is_some() => {
-                self.pointer.take().unwrap().release();
+                self.pointer.take().unwrap().pointer().release();
+                self.pointer_focus = None;
             }
             Capability::Keyboard if self.keyboard.is_some() => {
                 self.keyboard.take().unwrap().release();
+                if let Some(text_input) = self.text_input.take() {
+                    text_input.destroy();
+                }
+            }
+            Capability::Touch if self.touch.is_some() => {
+                self.touch.take().unwrap().release();
+                self.touch_points.clear();
+                self.primary_touch = None;
             }
             _ => {}
         }
 
         if capability == Capability::Pointer && self.pointer.is_some() {
-            self.pointer.take().unwrap().release();
+            self.pointer.take().unwrap().pointer().release();
         }
     }
 
@@ -410,3 +903,41 @@ impl ShmHandler for WgpuLayerShellState {
         &mut self.shm
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stretched_width_comes_from_the_compositor() {
+        // a bar anchored LEFT | RIGHT requests a width of 0 and gets the output's width
+        assert_eq!(configured_size((1920, 30), (0, 30), None), Some((1920, 30)));
+        // a compositor leaving the height to the client keeps the requested one
+        assert_eq!(configured_size((1920, 0), (0, 30), None), Some((1920, 30)));
+        // neither the compositor nor the request give a width
+        assert_eq!(configured_size((0, 30), (0, 30), None), None);
+    }
+
+    #[test]
+    fn zero_size_keeps_the_requested_size() {
+        assert_eq!(configured_size((0, 0), (400, 120), None), Some((400, 120)));
+        assert_eq!(configured_size((0, 0), (0, 0), None), None);
+    }
+
+    #[test]
+    fn size_is_clamped() {
+        let max_size = Some((2560, 1440));
+        assert_eq!(
+            configured_size((100_000, 30), (0, 30), max_size),
+            Some((2560, 30))
+        );
+        assert_eq!(
+            configured_size((0, 0), (400, 5000), max_size),
+            Some((400, 1440))
+        );
+        assert_eq!(
+            configured_size((1920, 1080), (0, 0), max_size),
+            Some((1920, 1080))
+        );
+    }
+}
@@ -0,0 +1,565 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use egui_software_backend::ColorFieldOrder;
+use smithay_client_toolkit::{
+    seat::keyboard::RepeatInfo,
+    shell::wlr_layer::{Anchor, KeyboardInteractivity, Layer},
+};
+use wayland_client::protocol::{wl_output, wl_shm};
+
+use super::LayerShellError;
+
+/// How the surface interacts with the exclusive zones of other surfaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExclusiveZone {
+    /// Reserve this many pixels from the anchored edge, e.g. for a bar.
+    Exclusive(u32),
+    /// Don't reserve space, but get moved out of the way of other exclusive zones.
+    Neutral,
+    /// Extend under other exclusive zones instead of being moved by them.
+    Ignore,
+}
+
+/// When a surface draws its first frame after the compositor configured it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FirstDraw {
+    /// Draw as soon as the surface is configured.
+    #[default]
+    Immediate,
+    /// Wait this long after the configure, e.g. to coalesce with other startup work.
+    After(Duration),
+    /// Wait for the app to request a frame, e.g. with [`crate::LayerShellHandle::request_redraw`]
+    /// once it is ready. The surface stays unmapped until then.
+    ///
+    /// Redraws the surface needs on its own, e.g. after a scale or theme change, don't count
+    /// as a request. The same goes for [`Self::After`], only the app can draw sooner.
+    OnRequest,
+}
+
+impl From<ExclusiveZone> for i32 {
+    fn from(zone: ExclusiveZone) -> Self {
+        match zone {
+            ExclusiveZone::Exclusive(size) => size.min(i32::MAX as u32) as i32,
+            ExclusiveZone::Neutral => 0,
+            ExclusiveZone::Ignore => -1,
+        }
+    }
+}
+
+/// Selects the output a surface is placed on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputSelector {
+    Output(wl_output::WlOutput),
+    /// Matches the output name announced by the compositor, e.g. `DP-1`.
+    Name(String),
+}
+
+/// Pixel format of the shm buffers the surface is rendered into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BufferFormat {
+    /// Blended with what is below the surface using the alpha channel.
+    #[default]
+    Argb8888,
+    /// Ignores the alpha channel, so the compositor can skip blending for opaque surfaces.
+    Xrgb8888,
+    Abgr8888,
+    Xbgr8888,
+}
+
+impl BufferFormat {
+    pub(crate) fn shm_format(self) -> wl_shm::Format {
+        match self {
+            BufferFormat::Argb8888 => wl_shm::Format::Argb8888,
+            BufferFormat::Xrgb8888 => wl_shm::Format::Xrgb8888,
+            BufferFormat::Abgr8888 => wl_shm::Format::Abgr8888,
+            BufferFormat::Xbgr8888 => wl_shm::Format::Xbgr8888,
+        }
+    }
+
+    /// Whether the compositor blends the buffer using its alpha channel.
+    pub(crate) fn has_alpha(self) -> bool {
+        matches!(self, BufferFormat::Argb8888 | BufferFormat::Abgr8888)
+    }
+
+    /// Order of the channels in memory.
+    ///
+    /// The `wl_shm` formats are defined as little endian on every host, e.g. `Argb8888` is
+    /// always stored as B, G, R, A. The renderer writes bytes rather than `u32`s, so the order
+    /// only depends on the format and not on the endianness of the machine.
+    pub(crate) fn color_order(self) -> ColorFieldOrder {
+        match self {
+            BufferFormat::Argb8888 | BufferFormat::Xrgb8888 => ColorFieldOrder::Bgra,
+            BufferFormat::Abgr8888 | BufferFormat::Xbgr8888 => ColorFieldOrder::Rgba,
+        }
+    }
+
+    /// Bytes of a premultiplied color as stored in a buffer of this format.
+    pub(crate) fn pixel(self, color: egui::Color32) -> [u8; 4] {
+        let [r, g, b, a] = color.to_array();
+        match self.color_order() {
+            ColorFieldOrder::Bgra => [b, g, r, a],
+            ColorFieldOrder::Rgba => [r, g, b, a],
+        }
+    }
+}
+
+/// Settings of the software renderer, the defaults suit UIs that change every frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RendererOptions {
+    /// Caches the rasterized shapes between frames, which saves a lot of work for mostly
+    /// static, text heavy UIs at the cost of memory. Off by default.
+    pub caching: bool,
+    /// Rasterizes simple shapes with faster special cases. On by default.
+    pub allow_raster_opt: bool,
+    /// Draws pairs of triangles forming axis aligned rectangles as rectangles. On by default.
+    pub convert_tris_to_rects: bool,
+    /// Threads rasterizing horizontal bands of the buffer in parallel, 1 by default.
+    ///
+    /// Helps with large surfaces, e.g. on 4K outputs. Each thread keeps its own copy of the
+    /// textures, so memory grows with the count.
+    pub threads: usize,
+}
+
+impl Default for RendererOptions {
+    fn default() -> Self {
+        Self {
+            caching: false,
+            allow_raster_opt: true,
+            convert_tris_to_rects: true,
+            threads: 1,
+        }
+    }
+}
+
+/// Returns the time in seconds that is passed to egui as `RawInput::time`.
+pub type Clock = Arc<dyn Fn() -> f64 + Send + Sync>;
+
+/// Namespace used if none is set, compositors may match rules against it.
+pub const DEFAULT_NAMESPACE: &str = env!("CARGO_PKG_NAME");
+
+pub struct LayerShellOptions {
+    pub layer: Option<Layer>,
+    /// Identifies the kind of surface to the compositor, e.g. `panel` or `launcher`.
+    ///
+    /// An empty namespace is replaced by [`DEFAULT_NAMESPACE`].
+    pub namespace: String,
+    /// Requested width, 0 lets the compositor stretch the surface between the left and right
+    /// anchors.
+    pub width: u32,
+    /// Requested height, 0 lets the compositor stretch the surface between the top and bottom
+    /// anchors.
+    pub height: u32,
+    pub anchor: Option<Anchor>,
+    /// Whether and how the surface receives keyboard focus, `None` keeps the compositor default
+    /// of no focus.
+    ///
+    /// With [`KeyboardInteractivity::OnDemand`] the compositor focuses the surface when it is
+    /// clicked and moves the focus away when another window is clicked, so typing only reaches
+    /// egui after a click. egui sees these transitions as [`egui::Event::WindowFocused`].
+    pub keyboard_interactivity: Option<KeyboardInteractivity>,
+    /// Gap between the surface and the anchored edges as `(top, right, bottom, left)`.
+    ///
+    /// Margins only apply to edges the surface is anchored to.
+    pub margin: Option<(i32, i32, i32, i32)>,
+    pub exclusive_zone: Option<ExclusiveZone>,
+    /// Output to place the surface on, the compositor chooses one if unset or not found.
+    pub output: Option<OutputSelector>,
+    /// Enables copy/paste through the regular wayland clipboard, on by default.
+    pub clipboard: bool,
+    /// Color the buffer is cleared with before rendering, transparent by default.
+    ///
+    /// The color is premultiplied like the buffer it is written to. An opaque color marks
+    /// the whole surface as opaque, so the compositor doesn't blend what is below it.
+    pub clear_color: egui::Color32,
+    /// Upper bound for the frames drawn per second, unlimited if unset.
+    ///
+    /// Repaint requests and input arriving in between are drawn together with the next
+    /// allowed frame.
+    pub max_fps: Option<u32>,
+    /// Overrides the key repeat delay and rate announced by the compositor.
+    ///
+    /// Repeats are sent to egui as further pressed `Event::Key` (and `Event::Text`) events
+    /// for the held key, [`RepeatInfo::Disable`] turns them off. The keyboard belongs to the
+    /// seat, so only the options the state is created with are used.
+    pub repeat_info: Option<RepeatInfo>,
+    /// Format of the buffers, falls back to [`BufferFormat::Argb8888`] if the compositor
+    /// doesn't support it.
+    pub format: BufferFormat,
+    /// Context to drive the surface with instead of a new one.
+    ///
+    /// Its repaint callback is replaced to schedule the surface's frames, so a context can't
+    /// be shared between surfaces.
+    pub context: Option<egui::Context>,
+    /// Initial size of the shm pool in bytes, by default large enough for two buffers of
+    /// the requested size. The pool grows when needed, but growing is expensive.
+    pub pool_size: Option<usize>,
+    /// Disables egui's animations by setting the `animation_time` of its styles to 0.
+    ///
+    /// Styles set later, e.g. in [`crate::App::setup`], have to keep it at 0 themselves.
+    pub reduced_motion: bool,
+    /// Time source for egui, seconds since the surface was created by default.
+    ///
+    /// Sharing a clock keeps animations of several surfaces in sync, tests can use it to
+    /// advance time deterministically.
+    pub clock: Option<Clock>,
+    /// Makes the surface click-through, e.g. for a HUD on [`Layer::Overlay`].
+    ///
+    /// The surface never gets keyboard focus and has an empty input region, so all input
+    /// goes to the windows below. Overrides `keyboard_interactivity` and
+    /// [`crate::App::input_region`], also when they change later, e.g. with
+    /// [`super::LayerShellSurface::set_keyboard_interactivity`].
+    pub passthrough: bool,
+    /// Fixes egui's `pixels_per_point` regardless of the scale the compositor reports, e.g.
+    /// 1.5 for larger text on a 1x output.
+    ///
+    /// The buffers keep the compositor's scale, so the UI stays sharp, egui zooms to reach the
+    /// requested value. This replaces the zoom set with [`egui::Context::set_zoom_factor`].
+    pub scale_override: Option<f32>,
+    /// Light or dark visuals, by default following the color scheme of the desktop.
+    ///
+    /// See [`crate::LayerShellHandle::set_system_theme`] for where the system theme is read.
+    pub theme: egui::ThemePreference,
+    /// Font files (TrueType or OpenType) preferred over egui's own fonts, in this order.
+    ///
+    /// They are used for the proportional and the monospace family. Files that can't be
+    /// loaded are skipped with a logged error.
+    pub fonts: Vec<PathBuf>,
+    /// When the first frame is drawn, right after the first configure by default.
+    pub first_draw: FirstDraw,
+    /// Keeps receiving pointer input while the keyboard focus is elsewhere, true by default.
+    ///
+    /// If false, presses, movement, scrolling and text arriving while the surface isn't
+    /// focused are dropped, e.g. for a popup that closes when it loses focus. Releases still
+    /// arrive, so nothing stays pressed. Such a surface needs keyboard interactivity to ever
+    /// receive input.
+    pub unfocused_input: bool,
+    /// Multiplies the scroll distance of wheels and touchpads, 1 by default.
+    pub scroll_speed: f32,
+    /// Draws a frame for every frame callback, even without repaint requests.
+    ///
+    /// Locks continuous animations to the refresh rate of the output, limited by `max_fps`.
+    pub continuous: bool,
+    /// Resizes the surface to the space the UI uses, e.g. for a panel listing a varying
+    /// number of items.
+    ///
+    /// The size is measured with [`egui::Context::used_rect`] from the surface's origin, so a
+    /// `CentralPanel` always fills the surface, use an [`egui::Area`] or a fixed size panel
+    /// instead. Axes stretched between two anchors keep the size the compositor chooses.
+    pub fit_content: bool,
+    /// Command that opens the links clicked in egui, e.g. [`egui::Hyperlink`], the url is
+    /// appended as last argument. `xdg-open` by default, `None` ignores links.
+    ///
+    /// Arguments are separated by whitespace, e.g. `flatpak-spawn --host xdg-open` in a sandbox.
+    pub url_opener: Option<String>,
+    /// Largest size in logical pixels a configure may set, larger ones are clamped.
+    ///
+    /// Guards the shm pool against huge sizes, e.g. from a layout bug. Defaults to the
+    /// largest width and height of the connected outputs.
+    pub max_size: Option<(u32, u32)>,
+    /// Tunes the software renderer to the workload, e.g. caching for text heavy UIs.
+    pub renderer: RendererOptions,
+}
+
+impl Default for LayerShellOptions {
+    fn default() -> Self {
+        Self {
+            layer: None,
+            namespace: DEFAULT_NAMESPACE.to_owned(),
+            width: 0,
+            height: 0,
+            anchor: None,
+            keyboard_interactivity: None,
+            margin: None,
+            exclusive_zone: None,
+            output: None,
+            clipboard: true,
+            clear_color: egui::Color32::TRANSPARENT,
+            max_fps: None,
+            repeat_info: None,
+            format: BufferFormat::default(),
+            context: None,
+            pool_size: None,
+            reduced_motion: false,
+            clock: None,
+            passthrough: false,
+            scale_override: None,
+            theme: egui::ThemePreference::System,
+            fonts: Vec::new(),
+            first_draw: FirstDraw::Immediate,
+            unfocused_input: true,
+            scroll_speed: 1.0,
+            continuous: false,
+            fit_content: false,
+            url_opener: Some("xdg-open".to_owned()),
+            max_size: None,
+            renderer: RendererOptions::default(),
+        }
+    }
+}
+
+impl LayerShellOptions {
+    pub fn builder() -> LayerShellOptionsBuilder {
+        LayerShellOptionsBuilder::default()
+    }
+
+    pub(crate) fn pool_size(&self) -> usize {
+        const MIN_POOL_SIZE: usize = 256 * 256 * 4;
+        self.pool_size.unwrap_or_else(|| {
+            // the buffers are double buffered, scaling may still make the pool grow
+            let buffer_size = self.width as usize * self.height as usize * 4;
+            (buffer_size * 2).max(MIN_POOL_SIZE)
+        })
+    }
+
+    pub(crate) fn namespace(&self) -> String {
+        if self.namespace.is_empty() {
+            DEFAULT_NAMESPACE.to_owned()
+        } else {
+            self.namespace.clone()
+        }
+    }
+}
+
+/// Builder for [`LayerShellOptions`], validating the combination of size and anchor.
+#[derive(Default)]
+pub struct LayerShellOptionsBuilder {
+    options: LayerShellOptions,
+}
+
+impl LayerShellOptionsBuilder {
+    pub fn layer(mut self, layer: Layer) -> Self {
+        self.options.layer = Some(layer);
+        self
+    }
+
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.options.namespace = namespace.into();
+        self
+    }
+
+    /// A dimension of 0 lets the compositor decide, which requires anchoring to both edges
+    /// of that axis.
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.options.width = width;
+        self.options.height = height;
+        self
+    }
+
+    pub fn anchor(mut self, anchor: Anchor) -> Self {
+        self.options.anchor = Some(anchor);
+        self
+    }
+
+    pub fn keyboard_interactivity(mut self, keyboard_interactivity: KeyboardInteractivity) -> Self {
+        self.options.keyboard_interactivity = Some(keyboard_interactivity);
+        self
+    }
+
+    pub fn margin(mut self, top: i32, right: i32, bottom: i32, left: i32) -> Self {
+        self.options.margin = Some((top, right, bottom, left));
+        self
+    }
+
+    pub fn exclusive_zone(mut self, exclusive_zone: ExclusiveZone) -> Self {
+        self.options.exclusive_zone = Some(exclusive_zone);
+        self
+    }
+
+    pub fn output(mut self, output: OutputSelector) -> Self {
+        self.options.output = Some(output);
+        self
+    }
+
+    pub fn clipboard(mut self, clipboard: bool) -> Self {
+        self.options.clipboard = clipboard;
+        self
+    }
+
+    pub fn clear_color(mut self, clear_color: egui::Color32) -> Self {
+        self.options.clear_color = clear_color;
+        self
+    }
+
+    pub fn max_fps(mut self, max_fps: u32) -> Self {
+        self.options.max_fps = Some(max_fps);
+        self
+    }
+
+    pub fn repeat_info(mut self, repeat_info: RepeatInfo) -> Self {
+        self.options.repeat_info = Some(repeat_info);
+        self
+    }
+
+    pub fn format(mut self, format: BufferFormat) -> Self {
+        self.options.format = format;
+        self
+    }
+
+    pub fn context(mut self, context: egui::Context) -> Self {
+        self.options.context = Some(context);
+        self
+    }
+
+    pub fn pool_size(mut self, pool_size: usize) -> Self {
+        self.options.pool_size = Some(pool_size);
+        self
+    }
+
+    pub fn reduced_motion(mut self, reduced_motion: bool) -> Self {
+        self.options.reduced_motion = reduced_motion;
+        self
+    }
+
+    pub fn clock(mut self, clock: impl Fn() -> f64 + Send + Sync + 'static) -> Self {
+        self.options.clock = Some(Arc::new(clock));
+        self
+    }
+
+    pub fn passthrough(mut self, passthrough: bool) -> Self {
+        self.options.passthrough = passthrough;
+        self
+    }
+
+    pub fn scale_override(mut self, pixels_per_point: f32) -> Self {
+        self.options.scale_override = Some(pixels_per_point);
+        self
+    }
+
+    pub fn theme(mut self, theme: egui::ThemePreference) -> Self {
+        self.options.theme = theme;
+        self
+    }
+
+    pub fn font(mut self, path: impl Into<PathBuf>) -> Self {
+        self.options.fonts.push(path.into());
+        self
+    }
+
+    pub fn scroll_speed(mut self, scroll_speed: f32) -> Self {
+        self.options.scroll_speed = scroll_speed;
+        self
+    }
+
+    pub fn continuous(mut self, continuous: bool) -> Self {
+        self.options.continuous = continuous;
+        self
+    }
+
+    pub fn first_draw(mut self, first_draw: FirstDraw) -> Self {
+        self.options.first_draw = first_draw;
+        self
+    }
+
+    pub fn fit_content(mut self, fit_content: bool) -> Self {
+        self.options.fit_content = fit_content;
+        self
+    }
+
+    pub fn unfocused_input(mut self, unfocused_input: bool) -> Self {
+        self.options.unfocused_input = unfocused_input;
+        self
+    }
+
+    pub fn url_opener(mut self, url_opener: Option<String>) -> Self {
+        self.options.url_opener = url_opener;
+        self
+    }
+
+    pub fn max_size(mut self, width: u32, height: u32) -> Self {
+        self.options.max_size = Some((width, height));
+        self
+    }
+
+    pub fn renderer(mut self, renderer: RendererOptions) -> Self {
+        self.options.renderer = renderer;
+        self
+    }
+
+    pub fn build(self) -> Result<LayerShellOptions, LayerShellError> {
+        let anchor = self.options.anchor.unwrap_or(Anchor::empty());
+        if self.options.width == 0 && !anchor.contains(Anchor::LEFT | Anchor::RIGHT) {
+            return Err(LayerShellError::ZeroWidth);
+        }
+        if self.options.height == 0 && !anchor.contains(Anchor::TOP | Anchor::BOTTOM) {
+            return Err(LayerShellError::ZeroHeight);
+        }
+        Ok(self.options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMATS: [BufferFormat; 4] = [
+        BufferFormat::Argb8888,
+        BufferFormat::Xrgb8888,
+        BufferFormat::Abgr8888,
+        BufferFormat::Xbgr8888,
+    ];
+
+    #[test]
+    fn color_order_follows_the_format() {
+        assert!(matches!(
+            BufferFormat::Argb8888.color_order(),
+            ColorFieldOrder::Bgra
+        ));
+        assert!(matches!(
+            BufferFormat::Xrgb8888.color_order(),
+            ColorFieldOrder::Bgra
+        ));
+        assert!(matches!(
+            BufferFormat::Abgr8888.color_order(),
+            ColorFieldOrder::Rgba
+        ));
+        assert!(matches!(
+            BufferFormat::Xbgr8888.color_order(),
+            ColorFieldOrder::Rgba
+        ));
+    }
+
+    /// The bytes in memory, the formats are little endian `u32`s, e.g. `Argb8888` is B, G, R, A.
+    fn expected_bytes(format: BufferFormat) -> [u8; 4] {
+        match format {
+            BufferFormat::Argb8888 | BufferFormat::Xrgb8888 => [30, 20, 10, 255],
+            BufferFormat::Abgr8888 | BufferFormat::Xbgr8888 => [10, 20, 30, 255],
+        }
+    }
+
+    #[test]
+    fn clear_color_byte_order() {
+        let color = egui::Color32::from_rgb(10, 20, 30);
+        for format in FORMATS {
+            assert_eq!(format.pixel(color), expected_bytes(format), "{format:?}");
+        }
+    }
+
+    struct Fill;
+
+    impl crate::App for Fill {
+        fn update(&mut self, ctx: &egui::Context) {
+            egui::CentralPanel::default()
+                .frame(egui::Frame::new().fill(egui::Color32::from_rgb(10, 20, 30)))
+                .show(ctx, |_ui| {});
+        }
+    }
+
+    #[test]
+    fn rendered_byte_order() {
+        for format in FORMATS {
+            let mut headless = crate::Headless::with_format(4, 4, 1.0, format).unwrap();
+            let mut pixels = vec![0; 4 * 4 * 4];
+            headless.step_into(&mut Fill, &mut pixels).unwrap();
+            assert_eq!(pixels[..4], expected_bytes(format), "{format:?}");
+        }
+    }
+
+    #[test]
+    fn image_is_rgba_for_every_format() {
+        for format in FORMATS {
+            let mut headless = crate::Headless::with_format(4, 4, 1.0, format).unwrap();
+            let image = headless.step(&mut Fill);
+            assert_eq!(image.pixels[..4], [10, 20, 30, 255], "{format:?}");
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use smithay_client_toolkit::output::OutputInfo;
+
+use super::{SurfaceId, WgpuLayerShellState};
+
+/// Output a surface is shown on, readable from [`crate::App::update`] to lay out relative
+/// to the screen.
+///
+/// It's the first output the surface entered, a surface spanning several outputs only sees one.
+#[derive(Debug, Clone)]
+pub struct SurfaceOutput {
+    /// Name, modes, scale and logical position and size as announced by the compositor.
+    pub info: OutputInfo,
+}
+
+impl SurfaceOutput {
+    /// Returns the output of the surface the context belongs to, `None` until the surface
+    /// entered an output.
+    pub fn from_context(ctx: &egui::Context) -> Option<Self> {
+        ctx.data(|data| data.get_temp(egui::Id::NULL))
+    }
+}
+
+impl WgpuLayerShellState {
+    /// Info of the output the surface is shown on, see [`SurfaceOutput`].
+    pub fn output_info(&self, id: SurfaceId) -> Option<OutputInfo> {
+        let surface = self.surface(id)?;
+        self.output_state.info(surface.outputs.first()?)
+    }
+
+    /// Publishes the surface's output to its context and redraws, as the layout may depend on it.
+    pub(crate) fn update_output_info(&mut self, id: SurfaceId) {
+        let info = self.output_info(id);
+        let Some(surface) = self.surface_mut(id) else {
+            return;
+        };
+        surface.egui_state.context().data_mut(|data| match info {
+            Some(info) => data.insert_temp(egui::Id::NULL, SurfaceOutput { info }),
+            None => data.remove::<SurfaceOutput>(egui::Id::NULL),
+        });
+        surface.request_redraw();
+    }
+}
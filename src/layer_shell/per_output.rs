@@ -0,0 +1,66 @@
+use wayland_client::protocol::wl_output::WlOutput;
+
+use super::{LayerShellOptions, OutputSelector, SurfaceId, WgpuLayerShellState};
+
+/// Surfaces replicated on every output, e.g. the bars of a multi monitor setup.
+pub(crate) struct PerOutput {
+    // creates the options of the surface for an output
+    options: Box<dyn FnMut(&WlOutput) -> LayerShellOptions>,
+    surfaces: Vec<(WlOutput, SurfaceId)>,
+}
+
+impl WgpuLayerShellState {
+    /// Adds a surface to every output, including outputs connected later.
+    ///
+    /// `options` is called for each output, the output it returns is replaced by the one the
+    /// surface is created for. Every surface has its own context and is drawn with
+    /// [`crate::App::update_surface`], the surface of a disconnected output is removed.
+    pub fn add_surface_per_output(
+        &mut self,
+        options: impl FnMut(&WlOutput) -> LayerShellOptions + 'static,
+    ) -> Vec<SurfaceId> {
+        let mut per_output = PerOutput {
+            options: Box::new(options),
+            surfaces: Vec::new(),
+        };
+        let outputs: Vec<_> = self.output_state.outputs().collect();
+        for output in outputs {
+            self.add_replica(&mut per_output, output);
+        }
+        let ids = per_output.surfaces.iter().map(|(_, id)| *id).collect();
+        self.per_output.push(per_output);
+        ids
+    }
+
+    fn add_replica(&mut self, per_output: &mut PerOutput, output: WlOutput) {
+        let mut options = (per_output.options)(&output);
+        options.output = Some(OutputSelector::Output(output.clone()));
+        match self.add_surface(options) {
+            Ok(id) => per_output.surfaces.push((output, id)),
+            Err(err) => println!("could not add a surface to the output: {err}"),
+        }
+    }
+
+    pub(crate) fn add_replicas(&mut self, output: &WlOutput) {
+        let mut per_output = std::mem::take(&mut self.per_output);
+        for per_output in &mut per_output {
+            self.add_replica(per_output, output.clone());
+        }
+        self.per_output = per_output;
+    }
+
+    pub(crate) fn remove_replicas(&mut self, output: &WlOutput) {
+        let mut removed = Vec::new();
+        for per_output in &mut self.per_output {
+            per_output.surfaces.retain(|(replicated, id)| {
+                let keep = replicated != output;
+                if !keep {
+                    removed.push(*id);
+                }
+                keep
+            });
+        }
+        self.surfaces
+            .retain(|surface| !removed.contains(&surface.id));
+    }
+}
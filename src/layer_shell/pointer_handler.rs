@@ -1,7 +1,7 @@
 use egui::{PointerButton, Vec2};
 use smithay_client_toolkit::{
     delegate_pointer,
-    seat::pointer::{PointerEvent, PointerEventKind, PointerHandler},
+    seat::pointer::{AxisScroll, PointerEvent, PointerEventKind, PointerHandler},
 };
 use wayland_client::{
     protocol::wl_pointer::{self},
@@ -9,6 +9,14 @@ use wayland_client::{
 };
 
 use super::WgpuLayerShellState;
+use crate::egui_state;
+
+// button codes from linux/input-event-codes.h
+const BTN_LEFT: u32 = 0x110;
+const BTN_RIGHT: u32 = 0x111;
+const BTN_MIDDLE: u32 = 0x112;
+const BTN_SIDE: u32 = 0x113;
+const BTN_EXTRA: u32 = 0x114;
 
 delegate_pointer!(WgpuLayerShellState);
 
@@ -21,20 +29,58 @@ impl PointerHandler for WgpuLayerShellState {
         events: &[PointerEvent],
     ) {
         for event in events {
-            let position = egui::pos2(event.position.0 as f32, event.position.1 as f32);
+            let Some(surface) = self.surface_by_wl_mut(&event.surface) else {
+                continue;
+            };
+            let id = surface.id;
+            match event.kind {
+                PointerEventKind::Enter { serial } => {
+                    self.pointer_serial = Some(serial);
+                    self.pointer_focus = Some(id);
+                    // the cursor has to be set again on every enter
+                    self.cursor_icon = None;
+                }
+                PointerEventKind::Leave { .. } if self.pointer_focus == Some(id) => {
+                    self.pointer_focus = None;
+                }
+                PointerEventKind::Press { serial, .. }
+                | PointerEventKind::Release { serial, .. } => {
+                    self.pointer_serial = Some(serial);
+                }
+                _ => {}
+            }
+            let Some(surface) = self.surface_mut(id) else {
+                continue;
+            };
+            let scroll_speed = surface.scroll_speed;
+            let egui_state = &mut surface.egui_state;
+
+            // for button events sctk reports the position of the last enter/motion,
+            // so clicks land where the cursor was drawn
+            let position = egui_state.to_points(event.position.0, event.position.1);
             let egui_event = match event.kind {
                 PointerEventKind::Enter { .. } | PointerEventKind::Motion { .. } => {
                     egui::Event::PointerMoved(position)
                 }
-                PointerEventKind::Leave { .. } => egui::Event::PointerGone,
-                PointerEventKind::Press { button, .. }
-                | PointerEventKind::Release { button, .. } => {
+                PointerEventKind::Leave { .. } => {
+                    // buttons held while leaving never get a release on this surface,
+                    // egui would otherwise keep dragging once the pointer comes back
+                    release_held_buttons(egui_state, position);
+                    egui::Event::PointerGone
+                }
+                PointerEventKind::Press { button, time, .. }
+                | PointerEventKind::Release { button, time, .. } => {
+                    egui_state.set_event_time(time);
+                    let pressed = matches!(event.kind, PointerEventKind::Press { .. });
+                    if pressed && button == BTN_MIDDLE {
+                        paste_primary_selection(egui_state);
+                    }
                     if let Some(button) = translate_button(button) {
                         egui::Event::PointerButton {
                             button,
-                            modifiers: self.egui_state.modifiers(),
+                            modifiers: egui_state.modifiers(),
                             pos: position,
-                            pressed: matches!(event.kind, PointerEventKind::Press { .. }),
+                            pressed,
                         }
                     } else {
                         continue;
@@ -43,25 +89,97 @@ impl PointerHandler for WgpuLayerShellState {
                 PointerEventKind::Axis {
                     horizontal,
                     vertical,
+                    source,
                     ..
-                } => egui::Event::MouseWheel {
-                    unit: egui::MouseWheelUnit::Point,
-                    delta: Vec2::new(-horizontal.absolute as f32, -vertical.absolute as f32),
-                    modifiers: self.egui_state.modifiers(),
-                },
+                } => {
+                    let wheel_steps = (wheel_steps(&horizontal), wheel_steps(&vertical));
+                    let (unit, delta) = if matches!(
+                        source,
+                        Some(wl_pointer::AxisSource::Wheel | wl_pointer::AxisSource::WheelTilt)
+                    ) && wheel_steps != (0.0, 0.0)
+                    {
+                        // wheels scroll in notches, egui turns them into its line height
+                        (
+                            egui::MouseWheelUnit::Line,
+                            -Vec2::new(wheel_steps.0, wheel_steps.1),
+                        )
+                    } else {
+                        // `absolute` carries the smooth (high resolution) value in surface
+                        // pixels, so touchpad scrolling isn't quantized to wheel clicks
+                        let delta = egui_state
+                            .to_points(-horizontal.absolute, -vertical.absolute)
+                            .to_vec2();
+                        (egui::MouseWheelUnit::Point, delta)
+                    };
+                    // frames containing only an axis stop have no delta, egui has no kinetic
+                    // scrolling of its own that they would have to end
+                    if delta == Vec2::ZERO {
+                        continue;
+                    }
+                    egui::Event::MouseWheel {
+                        unit,
+                        delta: delta * scroll_speed,
+                        modifiers: egui_state.modifiers(),
+                    }
+                }
             };
-            self.egui_state.push_event(egui_event);
+            egui_state.push_event(egui_event);
+        }
+
+        self.update_cursor();
+    }
+}
+
+/// Notches the wheel turned, high resolution wheels send fractions of a notch.
+fn wheel_steps(axis: &AxisScroll) -> f32 {
+    if axis.value120 != 0 {
+        axis.value120 as f32 / 120.0
+    } else {
+        // compositors without high resolution scrolling only send whole notches
+        axis.discrete as f32
+    }
+}
+
+/// Pastes the primary selection into the focused text field, like middle click does elsewhere.
+fn paste_primary_selection(egui_state: &mut egui_state::State) {
+    if !egui_state.context().wants_keyboard_input() {
+        return;
+    }
+    if let Some(text) = egui_state.clipboard().load_primary() {
+        egui_state.push_event(egui::Event::Paste(text));
+    }
+}
+
+fn release_held_buttons(egui_state: &mut egui_state::State, pos: egui::Pos2) {
+    let modifiers = egui_state.modifiers();
+    for button in [
+        PointerButton::Primary,
+        PointerButton::Secondary,
+        PointerButton::Middle,
+        PointerButton::Extra1,
+        PointerButton::Extra2,
+    ] {
+        if egui_state
+            .context()
+            .input(|input| input.pointer.button_down(button))
+        {
+            egui_state.push_event(egui::Event::PointerButton {
+                button,
+                modifiers,
+                pos,
+                pressed: false,
+            });
         }
     }
 }
 
 fn translate_button(button: u32) -> Option<PointerButton> {
     match button {
-        0x110 => Some(PointerButton::Primary),
-        0x111 => Some(PointerButton::Secondary),
-        0x112 => Some(PointerButton::Middle),
-        0x113 => Some(PointerButton::Extra1),
-        0x114 => Some(PointerButton::Extra2),
+        BTN_LEFT => Some(PointerButton::Primary),
+        BTN_RIGHT => Some(PointerButton::Secondary),
+        BTN_MIDDLE => Some(PointerButton::Middle),
+        BTN_SIDE => Some(PointerButton::Extra1),
+        BTN_EXTRA => Some(PointerButton::Extra2),
         _ => None,
     }
 }
@@ -0,0 +1,32 @@
+use wayland_client::{delegate_noop, Connection, Dispatch, QueueHandle};
+use wayland_protocols::wp::{
+    fractional_scale::v1::client::{
+        wp_fractional_scale_manager_v1::WpFractionalScaleManagerV1,
+        wp_fractional_scale_v1::{self, WpFractionalScaleV1},
+    },
+    viewporter::client::{wp_viewport::WpViewport, wp_viewporter::WpViewporter},
+};
+
+use super::{SurfaceId, WgpuLayerShellState};
+
+delegate_noop!(WgpuLayerShellState: WpFractionalScaleManagerV1);
+delegate_noop!(WgpuLayerShellState: WpViewporter);
+delegate_noop!(WgpuLayerShellState: WpViewport);
+
+impl Dispatch<WpFractionalScaleV1, SurfaceId> for WgpuLayerShellState {
+    fn event(
+        state: &mut Self,
+        _proxy: &WpFractionalScaleV1,
+        event: wp_fractional_scale_v1::Event,
+        id: &SurfaceId,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        if let wp_fractional_scale_v1::Event::PreferredScale { scale } = event {
+            if let Some(surface) = state.surface_mut(*id) {
+                // the scale is sent as a fraction with a denominator of 120
+                surface.set_scale(scale as f32 / 120.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use smithay_client_toolkit::{
+    delegate_session_lock,
+    session_lock::{
+        SessionLock, SessionLockHandler, SessionLockSurface, SessionLockSurfaceConfigure,
+    },
+};
+use wayland_client::{protocol::wl_output::WlOutput, Connection, QueueHandle};
+
+use super::{
+    surface::SurfaceRole, LayerShellError, LayerShellOptions, SurfaceId, WgpuLayerShellState,
+};
+
+/// The active lock with the lock surface of every output.
+pub(crate) struct ActiveLock {
+    lock: SessionLock,
+    // creates the options of the lock surface for an output
+    options: Box<dyn FnMut(&WlOutput) -> LayerShellOptions>,
+    surfaces: Vec<(WlOutput, SurfaceId)>,
+}
+
+impl ActiveLock {
+    fn ids(&self) -> Vec<SurfaceId> {
+        self.surfaces.iter().map(|(_, id)| *id).collect()
+    }
+}
+
+impl WgpuLayerShellState {
+    /// Locks the session and covers every output with a lock surface, e.g. for a lock screen.
+    ///
+    /// `options` is called for each output, also for outputs connected while the session is
+    /// locked. Only the settings that don't belong to layer surfaces are used, the compositor
+    /// chooses the size. The lock surfaces are drawn with [`crate::App::update_surface`] like
+    /// any other surface, the surface of a disconnected output is removed. Fails if the
+    /// compositor doesn't support ext-session-lock-v1 or the session is already locked by
+    /// this state.
+    pub fn lock(
+        &mut self,
+        options: impl FnMut(&WlOutput) -> LayerShellOptions + 'static,
+    ) -> Result<Vec<SurfaceId>, LayerShellError> {
+        if self.session_lock.is_some() {
+            return Err(LayerShellError::AlreadyLocked);
+        }
+        let lock = self
+            .session_lock_state
+            .lock(&self.queue_handle)
+            .map_err(LayerShellError::SessionLock)?;
+
+        let mut active_lock = ActiveLock {
+            lock,
+            options: Box::new(options),
+            surfaces: Vec::new(),
+        };
+        let outputs: Vec<_> = self.output_state.outputs().collect();
+        for output in outputs {
+            self.add_lock_surface(&mut active_lock, output);
+        }
+        let ids = active_lock.ids();
+        self.session_lock = Some(active_lock);
+        Ok(ids)
+    }
+
+    fn add_lock_surface(&mut self, active_lock: &mut ActiveLock, output: WlOutput) {
+        let (id, wl_surface) = self.new_surface_id();
+        let lock_surface =
+            active_lock
+                .lock
+                .create_lock_surface(wl_surface, &output, &self.queue_handle);
+        let options = (active_lock.options)(&output);
+        self.push_surface(id, SurfaceRole::Lock(lock_surface), options);
+        active_lock.surfaces.push((output, id));
+    }
+
+    /// Covers an output connected while the session is locked.
+    pub(crate) fn lock_output(&mut self, output: &WlOutput) {
+        let Some(mut active_lock) = self.session_lock.take() else {
+            return;
+        };
+        self.add_lock_surface(&mut active_lock, output.clone());
+        self.session_lock = Some(active_lock);
+    }
+
+    /// Removes the lock surface of a disconnected output.
+    pub(crate) fn unlock_output(&mut self, output: &WlOutput) {
+        let Some(active_lock) = &mut self.session_lock else {
+            return;
+        };
+        let mut removed = Vec::new();
+        active_lock.surfaces.retain(|(locked, id)| {
+            let keep = locked != output;
+            if !keep {
+                removed.push(*id);
+            }
+            keep
+        });
+        self.surfaces
+            .retain(|surface| !removed.contains(&surface.id));
+    }
+
+    /// Unlocks the session and destroys the lock surfaces.
+    pub fn unlock(&mut self) {
+        let Some(active_lock) = self.session_lock.take() else {
+            return;
+        };
+        active_lock.lock.unlock();
+        let ids = active_lock.ids();
+        self.surfaces.retain(|surface| !ids.contains(&surface.id));
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.session_lock
+            .as_ref()
+            .is_some_and(|active_lock| active_lock.lock.is_locked())
+    }
+}
+
+delegate_session_lock!(WgpuLayerShellState);
+impl SessionLockHandler for WgpuLayerShellState {
+    fn locked(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _lock: SessionLock) {
+        println!("session locked");
+    }
+
+    fn finished(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _lock: SessionLock) {
+        // the compositor denied the lock or ended it, e.g. because another client locked
+        println!("session lock finished");
+        if let Some(active_lock) = self.session_lock.take() {
+            let ids = active_lock.ids();
+            self.surfaces.retain(|surface| !ids.contains(&surface.id));
+        }
+    }
+
+    fn configure(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        lock_surface: SessionLockSurface,
+        configure: SessionLockSurfaceConfigure,
+        _serial: u32,
+    ) {
+        let Some(surface) = self.surface_by_wl_mut(lock_surface.wl_surface()) else {
+            return;
+        };
+        // lock surfaces have to use exactly the configured size, which covers the output
+        let (width, height) = configure.new_size;
+        if width == 0 || height == 0 {
+            println!("SessionLock configure: no size for {width}x{height}, ignoring");
+            return;
+        }
+        surface.configure(width, height);
+    }
+}
@@ -0,0 +1,165 @@
+use smithay_client_toolkit::seat::keyboard::{Keysym, Modifiers};
+
+use super::WgpuLayerShellState;
+
+/// A key combination handled before egui sees it, see [`WgpuLayerShellState::add_shortcut`].
+///
+/// The keysym is the one of the pressed key with the modifiers applied, e.g. `Keysym::D`
+/// rather than `Keysym::d` for Shift+d. Caps lock and num lock are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shortcut {
+    pub keysym: Keysym,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    /// The super or windows key.
+    pub logo: bool,
+}
+
+impl Shortcut {
+    /// The key without any modifier.
+    pub fn new(keysym: Keysym) -> Self {
+        Self {
+            keysym,
+            ctrl: false,
+            alt: false,
+            shift: false,
+            logo: false,
+        }
+    }
+
+    pub fn ctrl(mut self) -> Self {
+        self.ctrl = true;
+        self
+    }
+
+    pub fn alt(mut self) -> Self {
+        self.alt = true;
+        self
+    }
+
+    pub fn shift(mut self) -> Self {
+        self.shift = true;
+        self
+    }
+
+    pub fn logo(mut self) -> Self {
+        self.logo = true;
+        self
+    }
+
+    fn matches(&self, keysym: Keysym, modifiers: &Modifiers) -> bool {
+        self.keysym == keysym
+            && self.ctrl == modifiers.ctrl
+            && self.alt == modifiers.alt
+            && self.shift == modifiers.shift
+            && self.logo == modifiers.logo
+    }
+}
+
+pub(crate) type ShortcutAction = Box<dyn FnMut(&mut WgpuLayerShellState)>;
+
+/// Held keys whose press ran a shortcut, by their raw key code.
+///
+/// The keysym of the release depends on the modifiers held at that time, e.g. Shift let go
+/// before `d` releases `d` after `D` was pressed, so it can't identify the key.
+#[derive(Debug, Default)]
+pub(crate) struct ShortcutKeys(Vec<u32>);
+
+impl ShortcutKeys {
+    pub(crate) fn hold(&mut self, raw_code: u32) {
+        if !self.contains(raw_code) {
+            self.0.push(raw_code);
+        }
+    }
+
+    pub(crate) fn contains(&self, raw_code: u32) -> bool {
+        self.0.contains(&raw_code)
+    }
+
+    /// Forgets the key, returns whether its press ran a shortcut.
+    pub(crate) fn release(&mut self, raw_code: u32) -> bool {
+        let held = self.contains(raw_code);
+        self.0.retain(|held| *held != raw_code);
+        held
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+impl WgpuLayerShellState {
+    /// Calls `action` whenever `shortcut` is pressed on a focused surface, e.g. Super+D to
+    /// toggle a panel.
+    ///
+    /// Shortcuts are checked before the key reaches egui, a matching press isn't forwarded
+    /// to the UI, not even to a focused text field. Adding a shortcut again replaces its
+    /// action.
+    pub fn add_shortcut(
+        &mut self,
+        shortcut: Shortcut,
+        action: impl FnMut(&mut WgpuLayerShellState) + 'static,
+    ) {
+        self.remove_shortcut(shortcut);
+        self.shortcuts.push((shortcut, Box::new(action)));
+    }
+
+    pub fn remove_shortcut(&mut self, shortcut: Shortcut) {
+        self.shortcuts
+            .retain(|(registered, _)| *registered != shortcut);
+    }
+
+    /// Runs the action of the shortcut matching the pressed key, returns whether there was one.
+    pub(crate) fn dispatch_shortcut(&mut self, keysym: Keysym, raw_code: u32) -> bool {
+        let modifiers = self.modifiers;
+        let Some(index) = self
+            .shortcuts
+            .iter()
+            .position(|(shortcut, _)| shortcut.matches(keysym, &modifiers))
+        else {
+            return false;
+        };
+        // egui never saw the press, so neither repeats nor the release may reach it
+        self.shortcut_keys.hold(raw_code);
+        // the action may add or remove shortcuts itself
+        let (shortcut, mut action) = self.shortcuts.remove(index);
+        action(self);
+        if !self
+            .shortcuts
+            .iter()
+            .any(|(registered, _)| *registered == shortcut)
+        {
+            self.shortcuts
+                .insert(index.min(self.shortcuts.len()), (shortcut, action));
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // evdev code of the D key
+    const D: u32 = 32;
+
+    #[test]
+    fn modifier_released_first() {
+        let mut keys = ShortcutKeys::default();
+        // Ctrl+Shift+D pressed, Shift let go, then `d` released: the raw code stays the same
+        keys.hold(D);
+        assert!(keys.release(D));
+        assert!(!keys.contains(D));
+        assert!(!keys.release(D));
+    }
+
+    #[test]
+    fn repeated_press_held_once() {
+        let mut keys = ShortcutKeys::default();
+        keys.hold(D);
+        keys.hold(D);
+        assert!(keys.release(D));
+        assert!(!keys.contains(D));
+    }
+}
@@ -0,0 +1,1030 @@
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use smithay_client_toolkit::{
+    compositor::{CompositorState, Region},
+    session_lock::SessionLockSurface,
+    shell::{
+        wlr_layer::{Anchor, KeyboardInteractivity, Layer, LayerSurface},
+        WaylandSurface,
+    },
+    shm::slot::{Buffer, SlotPool},
+};
+use wayland_client::{
+    protocol::{wl_output::WlOutput, wl_surface::WlSurface},
+    QueueHandle,
+};
+use wayland_protocols::wp::{
+    fractional_scale::v1::client::wp_fractional_scale_v1::WpFractionalScaleV1,
+    viewporter::client::wp_viewport::WpViewport,
+};
+
+use super::{
+    damage::DamageTracker, BufferFormat, ExclusiveZone, FirstDraw, LayerShellError,
+    LayerShellOptions, SurfaceOutput, WgpuLayerShellState,
+};
+use crate::{egui_state, headless::to_rgba, App, FrameContext, FrameStats, Image};
+
+/// How long the configured size has to stay the same before the buffers are resized.
+const RESIZE_DEBOUNCE: Duration = Duration::from_millis(16);
+
+/// Identifies one of the layer surfaces driven by a [`WgpuLayerShellState`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SurfaceId(pub(crate) u32);
+
+/// Protocol object that gives a surface its role and configures it.
+pub(crate) enum SurfaceRole {
+    Layer(LayerSurface),
+    /// Covers an output while the session is locked, see [`WgpuLayerShellState::lock`].
+    Lock(SessionLockSurface),
+}
+
+impl SurfaceRole {
+    pub(crate) fn wl_surface(&self) -> &WlSurface {
+        match self {
+            SurfaceRole::Layer(layer) => layer.wl_surface(),
+            SurfaceRole::Lock(lock) => lock.wl_surface(),
+        }
+    }
+}
+
+/// Layer surface state chosen by the options and setters.
+///
+/// A layer surface unmapped with a null buffer forgets it, so it is sent again before the
+/// commit that maps the surface after [`LayerShellSurface::hide`].
+#[derive(Debug, Clone)]
+pub(crate) struct LayerState {
+    // only set once changed, the surface is created on its initial layer
+    layer: Option<Layer>,
+    // 0 lets the compositor decide that dimension
+    pub(crate) size: (u32, u32),
+    anchor: Option<Anchor>,
+    margin: Option<(i32, i32, i32, i32)>,
+    keyboard_interactivity: Option<KeyboardInteractivity>,
+    exclusive_zone: Option<ExclusiveZone>,
+}
+
+impl LayerState {
+    pub(crate) fn new(options: &LayerShellOptions) -> Self {
+        Self {
+            layer: None,
+            size: (options.width, options.height),
+            anchor: options.anchor,
+            margin: options.margin,
+            keyboard_interactivity: if options.passthrough {
+                Some(KeyboardInteractivity::None)
+            } else {
+                options.keyboard_interactivity
+            },
+            exclusive_zone: options.exclusive_zone,
+        }
+    }
+
+    /// Sends the whole state, the next commit applies it.
+    pub(crate) fn send(&self, layer: &impl LayerRequests) {
+        if let Some(layer_kind) = self.layer {
+            layer.set_layer(layer_kind);
+        }
+        if let Some(anchor) = self.anchor {
+            layer.set_anchor(anchor);
+        }
+        if let Some(keyboard_interactivity) = self.keyboard_interactivity {
+            layer.set_keyboard_interactivity(keyboard_interactivity);
+        }
+        if let Some((top, right, bottom, left)) = self.margin {
+            layer.set_margin(top, right, bottom, left);
+        }
+        if let Some(exclusive_zone) = self.exclusive_zone {
+            layer.set_exclusive_zone(exclusive_zone.into());
+        }
+        layer.set_size(self.size.0, self.size.1);
+    }
+}
+
+/// The requests setting the state of a [`LayerSurface`].
+pub(crate) trait LayerRequests {
+    fn set_layer(&self, layer: Layer);
+    fn set_size(&self, width: u32, height: u32);
+    fn set_anchor(&self, anchor: Anchor);
+    fn set_margin(&self, top: i32, right: i32, bottom: i32, left: i32);
+    fn set_keyboard_interactivity(&self, keyboard_interactivity: KeyboardInteractivity);
+    fn set_exclusive_zone(&self, exclusive_zone: i32);
+}
+
+impl LayerRequests for LayerSurface {
+    fn set_layer(&self, layer: Layer) {
+        LayerSurface::set_layer(self, layer);
+    }
+
+    fn set_size(&self, width: u32, height: u32) {
+        LayerSurface::set_size(self, width, height);
+    }
+
+    fn set_anchor(&self, anchor: Anchor) {
+        LayerSurface::set_anchor(self, anchor);
+    }
+
+    fn set_margin(&self, top: i32, right: i32, bottom: i32, left: i32) {
+        LayerSurface::set_margin(self, top, right, bottom, left);
+    }
+
+    fn set_keyboard_interactivity(&self, keyboard_interactivity: KeyboardInteractivity) {
+        LayerSurface::set_keyboard_interactivity(self, keyboard_interactivity);
+    }
+
+    fn set_exclusive_zone(&self, exclusive_zone: i32) {
+        LayerSurface::set_exclusive_zone(self, exclusive_zone);
+    }
+}
+
+/// A single layer surface with its own egui state, size and draw request.
+pub struct LayerShellSurface {
+    pub(crate) id: SurfaceId,
+    role: SurfaceRole,
+    // only set if the compositor supports fractional scaling
+    pub(crate) fractional_scale: Option<WpFractionalScaleV1>,
+    viewport: Option<WpViewport>,
+
+    // outputs the surface is currently shown on
+    pub(crate) outputs: Vec<WlOutput>,
+    pub(crate) layer_state: LayerState,
+    // resizes to the used rect of the UI
+    fit_content: bool,
+    // size requested before the current one when fitting, going back to it would oscillate
+    previous_fit: Option<(u32, u32)>,
+    // latest configured size with the time it arrived, applied once it settled
+    pending_size: Option<((u32, u32), Instant)>,
+
+    pub(crate) has_frame_callback: bool,
+    // timestamp of the last frame callback, passed to the app with the next frame
+    pub(crate) frame_time: Option<u32>,
+    pub(crate) is_configured: bool,
+    hidden: bool,
+    // the app is set up for the surface's context before the first frame
+    needs_setup: bool,
+    // when the first configure requests a frame, `Immediate` once it is drawn
+    first_draw: FirstDraw,
+
+    pub(crate) egui_state: egui_state::State,
+    pub(crate) draw_request: Arc<RwLock<Option<Instant>>>,
+    // minimum time between two frames, derived from `max_fps`
+    frame_interval: Option<Duration>,
+    last_draw: Option<Instant>,
+    // redraws for every frame callback
+    pub(crate) continuous: bool,
+
+    buffers: Vec<Buffer>,
+    // copies the next frame into `captured`
+    pub(crate) capture_requested: bool,
+    pub(crate) captured: Option<Image>,
+    // integer scale last set on the wl_surface, always 1 with a viewport
+    buffer_scale: i32,
+    clear_color: egui::Color32,
+    format: BufferFormat,
+    damage_tracker: DamageTracker,
+    // size the opaque region was last set for, `None` if the surface isn't opaque
+    opaque_size: Option<(i32, i32)>,
+    // last region returned by the app, in points, `None` if it has to be set again
+    input_region: Option<Option<Vec<egui::Rect>>>,
+    // ignores the app's input region, nothing receives input
+    passthrough: bool,
+    // multiplies the scroll deltas
+    pub(crate) scroll_speed: f32,
+    // configures are clamped to it, the largest output's size if unset
+    pub(crate) max_size: Option<(u32, u32)>,
+}
+
+impl LayerShellSurface {
+    pub(crate) fn new(
+        id: SurfaceId,
+        role: SurfaceRole,
+        fractional_scale: Option<WpFractionalScaleV1>,
+        viewport: Option<WpViewport>,
+        mut egui_state: egui_state::State,
+        draw_request: Arc<RwLock<Option<Instant>>>,
+        options: &LayerShellOptions,
+    ) -> Self {
+        // the requested size is used until the compositor configures one
+        if options.width != 0 && options.height != 0 {
+            egui_state.set_size(options.width, options.height);
+        }
+
+        Self {
+            id,
+            role,
+            fractional_scale,
+            viewport,
+
+            outputs: Vec::new(),
+            layer_state: LayerState::new(options),
+            fit_content: options.fit_content,
+            previous_fit: None,
+            pending_size: None,
+
+            has_frame_callback: false,
+            frame_time: None,
+            is_configured: false,
+            hidden: false,
+            needs_setup: true,
+            first_draw: options.first_draw,
+
+            egui_state,
+            draw_request,
+            frame_interval: options.max_fps.map(frame_interval),
+            last_draw: None,
+            continuous: options.continuous,
+
+            buffers: Vec::with_capacity(2),
+            capture_requested: false,
+            captured: None,
+            buffer_scale: 1,
+            clear_color: options.clear_color,
+            format: options.format,
+            damage_tracker: DamageTracker::default(),
+            opaque_size: None,
+            input_region: None,
+            passthrough: options.passthrough,
+            scroll_speed: options.scroll_speed,
+            max_size: options.max_size,
+        }
+    }
+
+    pub fn id(&self) -> SurfaceId {
+        self.id
+    }
+
+    pub(crate) fn wl_surface(&self) -> &WlSurface {
+        self.role.wl_surface()
+    }
+
+    /// The layer surface, `None` for lock surfaces, which have no layer state to change.
+    fn layer(&self) -> Option<&LayerSurface> {
+        match &self.role {
+            SurfaceRole::Layer(layer) => Some(layer),
+            SurfaceRole::Lock(_) => None,
+        }
+    }
+
+    pub fn context(&self) -> &egui::Context {
+        self.egui_state.context()
+    }
+
+    /// Moves the surface to another layer, e.g. from [`Layer::Top`] to [`Layer::Overlay`].
+    ///
+    /// Before the first configure this only updates the pending surface state,
+    /// it is applied together with the first frame.
+    pub fn set_layer(&mut self, layer: Layer) {
+        let Some(layer_surface) = self.layer() else {
+            return;
+        };
+        layer_surface.set_layer(layer);
+        if self.is_configured {
+            layer_surface.commit();
+        }
+        self.layer_state.layer = Some(layer);
+    }
+
+    /// Requests a size in logical pixels, 0 lets the compositor stretch the surface between
+    /// the anchors of that axis.
+    ///
+    /// The new size arrives with the next configure and is drawn in the following frame.
+    pub fn set_size(&mut self, width: u32, height: u32) {
+        let Some(layer) = self.layer() else {
+            return;
+        };
+        layer.set_size(width, height);
+        if self.is_configured {
+            layer.commit();
+        }
+        self.layer_state.size = (width, height);
+    }
+
+    /// Sets the margins as `(top, right, bottom, left)`.
+    ///
+    /// The compositor accounts for the margins in the size of the next configure.
+    pub fn set_margin(&mut self, top: i32, right: i32, bottom: i32, left: i32) {
+        let Some(layer) = self.layer() else {
+            return;
+        };
+        layer.set_margin(top, right, bottom, left);
+        if self.is_configured {
+            layer.commit();
+        }
+        self.layer_state.margin = Some((top, right, bottom, left));
+    }
+
+    /// Changes the edges the surface is anchored to.
+    ///
+    /// Anchoring to both edges of an axis with a size of 0 lets the compositor stretch the
+    /// surface, so changing the anchor may resize it. The new size arrives with the next
+    /// configure and is drawn in the following frame.
+    pub fn set_anchor(&mut self, anchor: Anchor) {
+        let Some(layer) = self.layer() else {
+            return;
+        };
+        layer.set_anchor(anchor);
+        if self.is_configured {
+            layer.commit();
+        }
+        self.layer_state.anchor = Some(anchor);
+    }
+
+    /// Changes whether and how the surface receives keyboard focus.
+    ///
+    /// Switching to [`KeyboardInteractivity::Exclusive`] makes the compositor move the focus
+    /// to the surface right away, so typing works without clicking it first. With
+    /// [`KeyboardInteractivity::OnDemand`] it is focused by the next click and with
+    /// [`KeyboardInteractivity::None`] the focus is taken away again.
+    ///
+    /// Ignored for a surface with [`LayerShellOptions::passthrough`], it never gets focus.
+    pub fn set_keyboard_interactivity(&mut self, keyboard_interactivity: KeyboardInteractivity) {
+        let Some(layer) = self.layer() else {
+            return;
+        };
+        if self.passthrough {
+            println!("not changing the keyboard interactivity of a passthrough surface");
+            return;
+        }
+        layer.set_keyboard_interactivity(keyboard_interactivity);
+        if self.is_configured {
+            layer.commit();
+        }
+        self.layer_state.keyboard_interactivity = Some(keyboard_interactivity);
+    }
+
+    pub fn set_exclusive_zone(&mut self, exclusive_zone: ExclusiveZone) {
+        let Some(layer) = self.layer() else {
+            return;
+        };
+        layer.set_exclusive_zone(exclusive_zone.into());
+        if self.is_configured {
+            layer.commit();
+        }
+        self.layer_state.exclusive_zone = Some(exclusive_zone);
+    }
+
+    pub fn set_clear_color(&mut self, clear_color: egui::Color32) {
+        self.clear_color = clear_color;
+        self.request_redraw();
+    }
+
+    /// Limits how many frames are drawn per second, `None` removes the limit.
+    pub fn set_max_fps(&mut self, max_fps: Option<u32>) {
+        self.frame_interval = max_fps.map(frame_interval);
+    }
+
+    /// Switches drawing a frame for every frame callback on or off, see
+    /// [`LayerShellOptions::continuous`].
+    pub fn set_continuous(&mut self, continuous: bool) {
+        self.continuous = continuous;
+        if continuous {
+            self.request_redraw();
+        }
+    }
+
+    /// Unmaps the surface until [`Self::show`] is called.
+    ///
+    /// Unlike [`crate::LayerShellHandle::exit`] the surface, its egui state and the buffers
+    /// stay alive, so showing it again is cheap. No frames are drawn while hidden. Lock
+    /// surfaces can't be hidden.
+    pub fn hide(&mut self) {
+        // lock surfaces may not be unmapped while the session is locked
+        if self.hidden || self.layer().is_none() {
+            return;
+        }
+        self.hidden = true;
+        self.is_configured = false;
+        // the compositor forgets the content, the first frame after showing is a full one
+        self.damage_tracker = DamageTracker::default();
+        self.opaque_size = None;
+        self.input_region = None;
+
+        let wl_surface = self.role.wl_surface();
+        wl_surface.attach(None, 0, 0);
+        wl_surface.commit();
+    }
+
+    /// Maps the surface again after [`Self::hide`].
+    ///
+    /// Like a new surface it is drawn once the compositor configured it, with the state set
+    /// while it was hidden.
+    pub fn show(&mut self) {
+        if !self.hidden {
+            return;
+        }
+        self.hidden = false;
+        if let Some(layer) = self.layer() {
+            self.layer_state.send(layer);
+        }
+        // an empty commit makes the compositor send a new configure
+        self.wl_surface().commit();
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
+    /// Whether egui used the pointer in the last frame, e.g. because it is over a widget or
+    /// a drag is in progress.
+    ///
+    /// Together with [`crate::App::input_region`] this allows making parts click-through that
+    /// egui doesn't use.
+    pub fn wants_pointer_input(&self) -> bool {
+        self.context().wants_pointer_input()
+    }
+
+    /// Whether a widget of the last frame has keyboard focus, e.g. a text field.
+    pub fn wants_keyboard_input(&self) -> bool {
+        self.context().wants_keyboard_input()
+    }
+
+    /// Timings and primitive count of the last drawn frame.
+    pub fn last_frame_stats(&self) -> FrameStats {
+        self.egui_state.frame_stats()
+    }
+
+    pub(crate) fn request_redraw(&self) {
+        // e.g. a scale or theme change doesn't bring a held first frame forward, the frame
+        // picks it up whenever it is drawn
+        if self.first_draw != FirstDraw::Immediate {
+            return;
+        }
+        *self.draw_request.write().unwrap() = Some(Instant::now());
+    }
+
+    /// Applies a configure of the surface's role object.
+    ///
+    /// sctk acked the serial already, so the next commit applies it. Frames are only
+    /// committed from `draw`, so the first content always follows the first ack.
+    pub(crate) fn configure(&mut self, width: u32, height: u32) {
+        if !self.is_configured {
+            self.is_configured = true;
+            self.has_frame_callback = true;
+            // a resize that was still settling when the surface was hidden is superseded
+            self.pending_size = None;
+            match self.first_draw {
+                FirstDraw::Immediate => self.request_redraw(),
+                FirstDraw::After(delay) => {
+                    *self.draw_request.write().unwrap() = Some(Instant::now() + delay);
+                }
+                // the app's request is kept if it came before the configure
+                FirstDraw::OnRequest => {}
+            }
+            self.egui_state.set_size(width, height);
+        } else {
+            // e.g. after the anchor changed, a later frame has to fill the new size
+            self.resize(width, height);
+        }
+    }
+
+    /// Requests the size of the space the last frame used, see [`LayerShellOptions::fit_content`].
+    fn fit_to_content(&mut self) {
+        // a resize in flight is still drawn at the old size, measuring it would chase itself
+        if !self.is_configured || self.pending_size.is_some() {
+            return;
+        }
+        let used = self
+            .egui_state
+            .to_logical(self.egui_state.context().used_rect());
+        let (stretch_x, stretch_y) = self.layer_state.anchor.map_or((false, false), |anchor| {
+            (
+                anchor.contains(Anchor::LEFT | Anchor::RIGHT),
+                anchor.contains(Anchor::TOP | Anchor::BOTTOM),
+            )
+        });
+        // stretched axes keep what was requested, usually 0 for the compositor to decide
+        let size = (
+            if stretch_x {
+                self.layer_state.size.0
+            } else {
+                (used.max.x.ceil() as u32).max(1)
+            },
+            if stretch_y {
+                self.layer_state.size.1
+            } else {
+                (used.max.y.ceil() as u32).max(1)
+            },
+        );
+        // content that reflows with the size, e.g. wrapped text, may alternate between two
+        // sizes, the current one is kept then
+        if size == self.layer_state.size || Some(size) == self.previous_fit {
+            return;
+        }
+        self.previous_fit = Some(self.layer_state.size);
+        // unlike `set_size` this doesn't commit, the frame's commit sends it with the buffer
+        if let Some(layer) = self.layer() {
+            layer.set_size(size.0, size.1);
+        }
+        self.layer_state.size = size;
+    }
+
+    /// Resizes the surface once no other size arrived for [`RESIZE_DEBOUNCE`].
+    ///
+    /// Interactive resizes send a configure per step, each of which would reallocate the
+    /// buffers. Frames drawn in between keep the previous size.
+    pub(crate) fn resize(&mut self, width: u32, height: u32) {
+        if self.egui_state.get_size() == Some((width as i32, height as i32)) {
+            self.pending_size = None;
+            return;
+        }
+
+        let now = Instant::now();
+        self.pending_size = Some(((width, height), now));
+
+        let mut draw_request = self.draw_request.write().unwrap();
+        let settled = now + RESIZE_DEBOUNCE;
+        *draw_request = Some(draw_request.map_or(settled, |time| time.min(settled)));
+    }
+
+    /// Applies the pending size if it settled, otherwise returns when it will have.
+    fn apply_pending_size(&mut self) -> Option<Instant> {
+        let ((width, height), since) = self.pending_size?;
+        let settled = since + RESIZE_DEBOUNCE;
+        if Instant::now() < settled {
+            return Some(settled);
+        }
+        self.pending_size = None;
+        self.egui_state.set_size(width, height);
+        None
+    }
+
+    pub(crate) fn set_scale(&mut self, scale: f32) {
+        if self.egui_state.scale() == scale {
+            return;
+        }
+        self.egui_state.set_scale(scale);
+        self.request_redraw();
+    }
+
+    /// Whether a frame could be committed right now.
+    ///
+    /// Content may only be committed once the configure it is drawn for was acked. sctk acks
+    /// every configure before the `configure` handler runs, which sets `is_configured` for
+    /// the first one.
+    fn can_draw(&self, pool: &mut SlotPool) -> bool {
+        self.is_mapped() && self.has_frame_callback && self.has_free_buffer(pool)
+    }
+
+    /// Whether a frame could be drawn ignoring the frame callback and pacing, e.g. when forced.
+    pub(crate) fn can_force_draw(&self, pool: &mut SlotPool) -> bool {
+        self.is_mapped() && self.has_free_buffer(pool)
+    }
+
+    /// Whether the surface is configured and not hidden, so frames are shown.
+    pub(crate) fn is_mapped(&self) -> bool {
+        !self.hidden && self.is_configured
+    }
+
+    pub(crate) fn should_draw(&self, pool: &mut SlotPool) -> bool {
+        if !self.can_draw(pool) {
+            return false;
+        }
+
+        match self.next_draw() {
+            Some(time) => time <= Instant::now(),
+            None => false,
+        }
+    }
+
+    pub(crate) fn get_timeout(&self, pool: &mut SlotPool) -> Option<Duration> {
+        // the release of a buffer arrives as a wayland event, which wakes the loop
+        if !self.can_draw(pool) {
+            return None;
+        }
+
+        self.next_draw()
+            .map(|instant| timeout_until(instant, Instant::now()))
+    }
+
+    /// Declares the whole surface as opaque if nothing below it can shine through, so the
+    /// compositor can skip blending it.
+    fn update_opaque_region(&mut self, compositor: &CompositorState, width: i32, height: i32) {
+        let opaque = !self.format.has_alpha() || self.clear_color.a() == u8::MAX;
+        let opaque_size = opaque.then_some((width, height));
+        if self.opaque_size == opaque_size {
+            return;
+        }
+        self.opaque_size = opaque_size;
+
+        let wl_surface = self.role.wl_surface();
+        if !opaque {
+            wl_surface.set_opaque_region(None);
+            return;
+        }
+        match Region::new(compositor) {
+            Ok(region) => {
+                region.add(0, 0, width, height);
+                // the region is copied, so it can be destroyed right away
+                wl_surface.set_opaque_region(Some(region.wl_region()));
+            }
+            Err(err) => println!("could not create the opaque region: {err}"),
+        }
+    }
+
+    fn update_input_region(
+        &mut self,
+        compositor: &CompositorState,
+        input_region: Option<Vec<egui::Rect>>,
+    ) {
+        if self.input_region.as_ref() == Some(&input_region) {
+            return;
+        }
+
+        let wl_surface = self.role.wl_surface();
+        match &input_region {
+            None => wl_surface.set_input_region(None),
+            Some(rects) => match Region::new(compositor) {
+                Ok(region) => {
+                    for rect in rects {
+                        let rect = self.egui_state.to_logical(*rect);
+                        let (min, max) = (rect.min.floor(), rect.max.ceil());
+                        region.add(
+                            min.x as i32,
+                            min.y as i32,
+                            (max.x - min.x) as i32,
+                            (max.y - min.y) as i32,
+                        );
+                    }
+                    wl_surface.set_input_region(Some(region.wl_region()));
+                }
+                Err(err) => {
+                    println!("could not create the input region: {err}");
+                    return;
+                }
+            },
+        }
+        self.input_region = Some(input_region);
+    }
+
+    /// Whether a frame can be drawn without touching a buffer the compositor still reads.
+    ///
+    /// Frames are deferred while both buffers are busy, a resize replaces them with new ones.
+    fn has_free_buffer(&self, pool: &mut SlotPool) -> bool {
+        let Some((buffer_w, buffer_h)) = self.egui_state.get_buffer_size() else {
+            return false;
+        };
+        let resized = self
+            .buffers
+            .iter()
+            .any(|buffer| buffer.height() != buffer_h || buffer.stride() != buffer_w * 4);
+        resized || choose_buffer(&self.free_buffers(pool)) != BufferChoice::Skip
+    }
+
+    /// Which of the buffers the compositor released, i.e. can be drawn into.
+    fn free_buffers(&self, pool: &mut SlotPool) -> Vec<bool> {
+        self.buffers
+            .iter()
+            .map(|buffer| buffer.canvas(pool).is_some())
+            .collect()
+    }
+
+    /// The time the next frame is due, delayed until the frame rate cap allows drawing again.
+    fn next_draw(&self) -> Option<Instant> {
+        let requested = if self.egui_state.has_pending_events() {
+            Some(Instant::now())
+        } else {
+            *self.draw_request.read().unwrap()
+        }?;
+
+        Some(earliest_draw(
+            requested,
+            self.last_draw,
+            self.frame_interval,
+        ))
+    }
+
+    pub(crate) fn draw(
+        &mut self,
+        pool: &mut SlotPool,
+        compositor: &CompositorState,
+        queue_handle: &QueueHandle<WgpuLayerShellState>,
+        application: &mut dyn App,
+    ) -> Result<(), LayerShellError> {
+        let resize_settles = self.apply_pending_size();
+
+        // nothing can be drawn before either the options or a configure provide a size
+        let (Some((w, h)), Some((buffer_w, buffer_h))) = (
+            self.egui_state.get_size(),
+            self.egui_state.get_buffer_size(),
+        ) else {
+            return Ok(());
+        };
+
+        // the buffers are reused across frames and only recreated once the size changes. The
+        // buffer is picked before egui runs, so a skipped frame doesn't lose egui's output
+        if self
+            .buffers
+            .first()
+            .is_some_and(|buffer| buffer.height() != buffer_h || buffer.stride() != buffer_w * 4)
+        {
+            self.buffers.clear();
+        }
+
+        let index = match choose_buffer(&self.free_buffers(pool)) {
+            BufferChoice::Reuse(index) => index,
+            BufferChoice::Create => {
+                let (buffer, _) = pool
+                    .create_buffer(buffer_w, buffer_h, buffer_w * 4, self.format.shm_format())
+                    .map_err(|source| LayerShellError::Buffer {
+                        width: buffer_w,
+                        height: buffer_h,
+                        source,
+                    })?;
+                self.buffers.push(buffer);
+                self.buffers.len() - 1
+            }
+            // nothing ran yet, the request stays and the buffer's release brings the frame
+            BufferChoice::Skip => return Ok(()),
+        };
+
+        // this frame keeps the previous size, the resize needs another one
+        *self.draw_request.write().unwrap() = resize_settles;
+        self.last_draw = Some(Instant::now());
+
+        // only the very first frame waits, a surface shown again draws right away
+        self.first_draw = FirstDraw::Immediate;
+        if self.needs_setup {
+            self.needs_setup = false;
+            application.setup(self.egui_state.context());
+        }
+
+        if let Some(time) = self.frame_time.take() {
+            application.on_frame(time);
+        }
+
+        self.egui_state
+            .input()
+            .events
+            .retain(|event| !application.raw_event(event));
+
+        let frame = FrameContext {
+            surface: self.id,
+            logical_size: (w as u32, h as u32),
+            scale: self.egui_state.scale(),
+            output_name: SurfaceOutput::from_context(self.egui_state.context())
+                .and_then(|output| output.info.name),
+        };
+        let full_output = self
+            .egui_state
+            .process_events(|ctx| application.update_frame(&frame, ctx));
+        if self.fit_content {
+            self.fit_to_content();
+        }
+
+        let buffer = &self.buffers[index];
+        let canvas = buffer
+            .canvas(pool)
+            .expect("buffer is not in use by the compositor");
+
+        // clear old buffer*
+        if self.clear_color == egui::Color32::TRANSPARENT {
+            canvas.fill(0);
+        } else {
+            let pixel = self.format.pixel(self.clear_color);
+            for chunk in canvas.chunks_exact_mut(4) {
+                chunk.copy_from_slice(&pixel);
+            }
+        }
+
+        self.egui_state
+            .draw(full_output, canvas, buffer_w as usize, buffer_h as usize)?;
+
+        if self.capture_requested {
+            self.capture_requested = false;
+            self.captured = Some(capture(
+                canvas,
+                buffer_w as u32,
+                buffer_h as u32,
+                self.format,
+            ));
+        }
+
+        buffer
+            .attach_to(self.role.wl_surface())
+            .expect("buffer attach");
+
+        // with fractional scaling the buffer is scaled down to the logical size by the viewport
+        if let Some(viewport) = &self.viewport {
+            viewport.set_destination(w, h);
+        } else {
+            // the buffer scale applies to the attached buffer, so it changes with the first
+            // buffer drawn at the new scale, the previous buffer might not be divisible by it
+            let buffer_scale = self.egui_state.scale().round() as i32;
+            if buffer_scale != self.buffer_scale {
+                self.buffer_scale = buffer_scale;
+                self.role.wl_surface().set_buffer_scale(buffer_scale);
+            }
+        }
+
+        self.update_opaque_region(compositor, w, h);
+        let input_region = if self.passthrough {
+            Some(Vec::new())
+        } else {
+            application.input_region(self.egui_state.context())
+        };
+        self.update_input_region(compositor, input_region);
+
+        // attach content, only damaging what changed since the last frame. The damage is in
+        // buffer pixels, so it stays correct with either kind of scaling
+        for (x, y, width, height) in self.damage_tracker.damage(canvas, buffer_w, buffer_h) {
+            self.role.wl_surface().damage_buffer(x, y, width, height);
+        }
+
+        // only a committed frame waits for its callback, a frame that failed before attaching
+        // left the surface untouched, so it can be drawn again right away
+        self.has_frame_callback = false;
+        self.role
+            .wl_surface()
+            .frame(queue_handle, self.role.wl_surface().clone());
+
+        self.role.wl_surface().commit();
+
+        Ok(())
+    }
+}
+
+impl Drop for LayerShellSurface {
+    fn drop(&mut self) {
+        // the extension objects have to go before the wl_surface, which the role destroys
+        if let Some(viewport) = self.viewport.take() {
+            viewport.destroy();
+        }
+        if let Some(fractional_scale) = self.fractional_scale.take() {
+            fractional_scale.destroy();
+        }
+    }
+}
+
+/// Buffer the next frame is drawn into.
+#[derive(Debug, PartialEq, Eq)]
+enum BufferChoice {
+    /// A buffer the compositor released, its content may be overwritten.
+    Reuse(usize),
+    /// There are fewer than two buffers, another one is allocated.
+    Create,
+    /// The compositor still holds every buffer, the frame has to wait for a release.
+    Skip,
+}
+
+/// Picks the buffer to draw into from which buffers are free, never more than two are kept.
+fn choose_buffer(free: &[bool]) -> BufferChoice {
+    match free.iter().position(|free| *free) {
+        Some(index) => BufferChoice::Reuse(index),
+        None if free.len() < 2 => BufferChoice::Create,
+        None => BufferChoice::Skip,
+    }
+}
+
+/// Copies a frame out of the buffer, as RGBA like the images rendered headless.
+fn capture(canvas: &[u8], width: u32, height: u32, format: BufferFormat) -> Image {
+    let mut pixels = canvas.to_vec();
+    to_rgba(&mut pixels, format.color_order());
+    Image {
+        width,
+        height,
+        pixels,
+    }
+}
+
+fn frame_interval(max_fps: u32) -> Duration {
+    Duration::from_secs(1) / max_fps.max(1)
+}
+
+/// Delays a requested frame until the frame interval since the last one has passed.
+fn earliest_draw(
+    requested: Instant,
+    last_draw: Option<Instant>,
+    frame_interval: Option<Duration>,
+) -> Instant {
+    let allowed = last_draw
+        .zip(frame_interval)
+        .map(|(last_draw, frame_interval)| last_draw + frame_interval);
+    allowed.map_or(requested, |allowed| requested.max(allowed))
+}
+
+fn timeout_until(instant: Instant, now: Instant) -> Duration {
+    // an overdue frame is drawn right away instead of computing a negative duration
+    instant.saturating_duration_since(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    /// Keeps the last value of every request.
+    #[derive(Default)]
+    struct RecordedLayer {
+        layer: RefCell<Option<Layer>>,
+        size: RefCell<Option<(u32, u32)>>,
+        anchor: RefCell<Option<Anchor>>,
+        margin: RefCell<Option<(i32, i32, i32, i32)>>,
+        exclusive_zone: RefCell<Option<i32>>,
+    }
+
+    impl LayerRequests for RecordedLayer {
+        fn set_layer(&self, layer: Layer) {
+            *self.layer.borrow_mut() = Some(layer);
+        }
+
+        fn set_size(&self, width: u32, height: u32) {
+            *self.size.borrow_mut() = Some((width, height));
+        }
+
+        fn set_anchor(&self, anchor: Anchor) {
+            *self.anchor.borrow_mut() = Some(anchor);
+        }
+
+        fn set_margin(&self, top: i32, right: i32, bottom: i32, left: i32) {
+            *self.margin.borrow_mut() = Some((top, right, bottom, left));
+        }
+
+        fn set_keyboard_interactivity(&self, _keyboard_interactivity: KeyboardInteractivity) {}
+
+        fn set_exclusive_zone(&self, exclusive_zone: i32) {
+            *self.exclusive_zone.borrow_mut() = Some(exclusive_zone);
+        }
+    }
+
+    #[test]
+    fn show_sends_the_size_set_while_hidden() {
+        let options = LayerShellOptions {
+            width: 400,
+            height: 30,
+            anchor: Some(Anchor::TOP),
+            ..Default::default()
+        };
+        let mut state = LayerState::new(&options);
+        // what `set_size` records while the surface is hidden
+        state.size = (800, 40);
+
+        // what `show` sends before its commit
+        let layer = RecordedLayer::default();
+        state.send(&layer);
+        assert_eq!(*layer.size.borrow(), Some((800, 40)));
+        assert_eq!(*layer.anchor.borrow(), Some(Anchor::TOP));
+        // the surface was created on its layer, it is only sent once changed
+        assert!(layer.layer.borrow().is_none());
+    }
+
+    #[test]
+    fn show_sends_the_changed_state() {
+        let mut state = LayerState::new(&LayerShellOptions::default());
+        state.layer = Some(Layer::Overlay);
+        state.margin = Some((1, 2, 3, 4));
+        state.exclusive_zone = Some(ExclusiveZone::Ignore);
+
+        let layer = RecordedLayer::default();
+        state.send(&layer);
+        assert!(matches!(*layer.layer.borrow(), Some(Layer::Overlay)));
+        assert_eq!(*layer.margin.borrow(), Some((1, 2, 3, 4)));
+        assert_eq!(*layer.exclusive_zone.borrow(), Some(-1));
+    }
+
+    #[test]
+    fn buffer_held_across_frames() {
+        // the first frame is attached while the compositor still shows the one before it
+        assert_eq!(choose_buffer(&[]), BufferChoice::Create);
+        assert_eq!(choose_buffer(&[false]), BufferChoice::Create);
+        // the next frame goes into the other buffer instead of overwriting the held one
+        assert_eq!(choose_buffer(&[false, true]), BufferChoice::Reuse(1));
+        assert_eq!(choose_buffer(&[true, false]), BufferChoice::Reuse(0));
+        // both held, nothing is overwritten or allocated
+        assert_eq!(choose_buffer(&[false, false]), BufferChoice::Skip);
+    }
+
+    #[test]
+    fn free_buffer_is_reused() {
+        assert_eq!(choose_buffer(&[true]), BufferChoice::Reuse(0));
+        assert_eq!(choose_buffer(&[true, true]), BufferChoice::Reuse(0));
+    }
+
+    #[test]
+    fn past_draw_request_times_out_immediately() {
+        let now = Instant::now();
+        let requested = now - Duration::from_secs(1);
+        let next_draw = earliest_draw(requested, None, None);
+        assert_eq!(timeout_until(next_draw, now), Duration::ZERO);
+
+        // a frame interval that has long passed does not delay it either
+        let last_draw = now - Duration::from_secs(2);
+        let next_draw = earliest_draw(requested, Some(last_draw), Some(Duration::from_millis(16)));
+        assert_eq!(timeout_until(next_draw, now), Duration::ZERO);
+    }
+
+    #[test]
+    fn draw_request_waits_for_the_frame_interval() {
+        let now = Instant::now();
+        let next_draw = earliest_draw(now, Some(now), Some(Duration::from_millis(16)));
+        assert_eq!(timeout_until(next_draw, now), Duration::from_millis(16));
+
+        let later = now + Duration::from_millis(100);
+        assert_eq!(
+            timeout_until(earliest_draw(later, None, None), now),
+            Duration::from_millis(100)
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use wayland_client::{delegate_noop, Connection, Dispatch, QueueHandle};
+use wayland_protocols::wp::text_input::zv3::client::{
+    zwp_text_input_manager_v3::ZwpTextInputManagerV3,
+    zwp_text_input_v3::{self, ContentHint, ContentPurpose, ZwpTextInputV3},
+};
+
+use super::{SurfaceId, WgpuLayerShellState};
+
+delegate_noop!(WgpuLayerShellState: ZwpTextInputManagerV3);
+
+/// Input method state of the seat, only enabled while a text field of the focused surface
+/// wants text input.
+pub(crate) struct TextInput {
+    text_input: ZwpTextInputV3,
+    focus: Option<SurfaceId>,
+    enabled: bool,
+    cursor_rect: Option<egui::Rect>,
+    // applied on `done`
+    pending_preedit: Option<String>,
+    pending_commit: Option<String>,
+}
+
+impl TextInput {
+    pub(crate) fn new(text_input: ZwpTextInputV3) -> Self {
+        Self {
+            text_input,
+            focus: None,
+            enabled: false,
+            cursor_rect: None,
+            pending_preedit: None,
+            pending_commit: None,
+        }
+    }
+
+    pub(crate) fn destroy(self) {
+        self.text_input.destroy();
+    }
+}
+
+impl WgpuLayerShellState {
+    /// Enables or disables the input method depending on the ime output of the last frame.
+    pub(crate) fn update_text_input(&mut self, id: SurfaceId) {
+        let Some(text_input) = &mut self.text_input else {
+            return;
+        };
+        if text_input.focus != Some(id) {
+            return;
+        }
+        let Some(surface) = self.surfaces.iter_mut().find(|surface| surface.id == id) else {
+            return;
+        };
+        let cursor_rect = surface
+            .egui_state
+            .ime()
+            .map(|ime| surface.egui_state.to_logical(ime.cursor_rect));
+
+        match (cursor_rect, text_input.enabled) {
+            (Some(cursor_rect), enabled) => {
+                if !enabled {
+                    text_input.text_input.enable();
+                    text_input
+                        .text_input
+                        .set_content_type(ContentHint::None, ContentPurpose::Normal);
+                    text_input.enabled = true;
+                    surface
+                        .egui_state
+                        .push_event(egui::Event::Ime(egui::ImeEvent::Enabled));
+                } else if text_input.cursor_rect == Some(cursor_rect) {
+                    return;
+                }
+                text_input.cursor_rect = Some(cursor_rect);
+                text_input.text_input.set_cursor_rectangle(
+                    cursor_rect.min.x as i32,
+                    cursor_rect.min.y as i32,
+                    cursor_rect.width() as i32,
+                    cursor_rect.height() as i32,
+                );
+                text_input.text_input.commit();
+            }
+            (None, true) => {
+                text_input.text_input.disable();
+                text_input.text_input.commit();
+                text_input.enabled = false;
+                text_input.cursor_rect = None;
+                surface
+                    .egui_state
+                    .push_event(egui::Event::Ime(egui::ImeEvent::Disabled));
+            }
+            (None, false) => {}
+        }
+    }
+}
+
+impl Dispatch<ZwpTextInputV3, ()> for WgpuLayerShellState {
+    fn event(
+        state: &mut Self,
+        _proxy: &ZwpTextInputV3,
+        event: zwp_text_input_v3::Event,
+        _data: &(),
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+    ) {
+        let focus = state
+            .text_input
+            .as_ref()
+            .and_then(|text_input| text_input.focus);
+        match event {
+            zwp_text_input_v3::Event::Enter { surface } => {
+                let id = state.surface_by_wl_mut(&surface).map(|surface| surface.id);
+                if let Some(text_input) = &mut state.text_input {
+                    text_input.focus = id;
+                }
+                if let Some(surface) = state.surface_by_wl_mut(&surface) {
+                    // make sure the next frame re-evaluates whether text input is wanted
+                    surface.request_redraw();
+                }
+            }
+            zwp_text_input_v3::Event::Leave { .. } => {
+                let Some(text_input) = &mut state.text_input else {
+                    return;
+                };
+                let was_enabled = text_input.enabled;
+                text_input.focus = None;
+                text_input.enabled = false;
+                text_input.cursor_rect = None;
+                text_input.text_input.disable();
+                text_input.text_input.commit();
+                if let Some(surface) = focus.and_then(|id| state.surface_mut(id)) {
+                    if was_enabled {
+                        surface
+                            .egui_state
+                            .push_event(egui::Event::Ime(egui::ImeEvent::Disabled));
+                    }
+                }
+            }
+            zwp_text_input_v3::Event::PreeditString { text, .. } => {
+                if let Some(text_input) = &mut state.text_input {
+                    text_input.pending_preedit = Some(text.unwrap_or_default());
+                }
+            }
+            zwp_text_input_v3::Event::CommitString { text } => {
+                if let Some(text_input) = &mut state.text_input {
+                    text_input.pending_commit = text;
+                }
+            }
+            zwp_text_input_v3::Event::Done { .. } => {
+                let Some(text_input) = &mut state.text_input else {
+                    return;
+                };
+                let commit = text_input.pending_commit.take();
+                // an empty preedit clears the previous one
+                let preedit = text_input.pending_preedit.take().unwrap_or_default();
+                if let Some(surface) = focus.and_then(|id| state.surface_mut(id)) {
+                    if let Some(commit) = commit {
+                        surface
+                            .egui_state
+                            .push_event(egui::Event::Ime(egui::ImeEvent::Commit(commit)));
+                    }
+                    surface
+                        .egui_state
+                        .push_event(egui::Event::Ime(egui::ImeEvent::Preedit(preedit)));
+                }
+            }
+            _ => {}
+        }
+    }
+}
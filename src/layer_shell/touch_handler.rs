@@ -0,0 +1,153 @@
+use smithay_client_toolkit::{delegate_touch, seat::touch::TouchHandler};
+use wayland_client::{
+    protocol::{wl_surface::WlSurface, wl_touch::WlTouch},
+    Connection, QueueHandle,
+};
+
+use super::{SurfaceId, WgpuLayerShellState};
+
+delegate_touch!(WgpuLayerShellState);
+
+impl WgpuLayerShellState {
+    fn push_touch_event(
+        &mut self,
+        surface: SurfaceId,
+        id: i32,
+        phase: egui::TouchPhase,
+        // surface-local, converted to points here
+        pos: egui::Pos2,
+    ) {
+        let is_primary = self.primary_touch == Some(id);
+        if is_primary && matches!(phase, egui::TouchPhase::End | egui::TouchPhase::Cancel) {
+            self.primary_touch = None;
+        }
+        let Some(surface) = self.surface_mut(surface) else {
+            return;
+        };
+        let egui_state = &mut surface.egui_state;
+        let pos = egui_state.to_points(pos.x as f64, pos.y as f64);
+
+        egui_state.push_event(egui::Event::Touch {
+            device_id: egui::TouchDeviceId(0),
+            id: egui::TouchId(id as u64),
+            phase,
+            pos,
+            force: None,
+        });
+
+        // the first finger also drives the pointer, so regular widgets respond to taps
+        if !is_primary {
+            return;
+        }
+        let modifiers = egui_state.modifiers();
+        let button = |pressed| egui::Event::PointerButton {
+            pos,
+            button: egui::PointerButton::Primary,
+            pressed,
+            modifiers,
+        };
+        match phase {
+            egui::TouchPhase::Start => {
+                egui_state.push_event(egui::Event::PointerMoved(pos));
+                egui_state.push_event(button(true));
+            }
+            egui::TouchPhase::Move => {
+                egui_state.push_event(egui::Event::PointerMoved(pos));
+            }
+            egui::TouchPhase::End | egui::TouchPhase::Cancel => {
+                egui_state.push_event(button(false));
+                egui_state.push_event(egui::Event::PointerGone);
+            }
+        }
+    }
+}
+
+impl TouchHandler for WgpuLayerShellState {
+    fn down(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _touch: &WlTouch,
+        _serial: u32,
+        time: u32,
+        surface: WlSurface,
+        id: i32,
+        position: (f64, f64),
+    ) {
+        let Some(surface) = self.surface_by_wl_mut(&surface) else {
+            return;
+        };
+        surface.egui_state.set_event_time(time);
+        let surface = surface.id;
+        let pos = egui::pos2(position.0 as f32, position.1 as f32);
+        self.touch_points.insert(id, (surface, pos));
+        if self.primary_touch.is_none() {
+            self.primary_touch = Some(id);
+        }
+        self.push_touch_event(surface, id, egui::TouchPhase::Start, pos);
+    }
+
+    fn up(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _touch: &WlTouch,
+        _serial: u32,
+        time: u32,
+        id: i32,
+    ) {
+        // up events carry no position, so reuse the last known one
+        if let Some((surface, pos)) = self.touch_points.remove(&id) {
+            if let Some(surface) = self.surface_mut(surface) {
+                surface.egui_state.set_event_time(time);
+            }
+            self.push_touch_event(surface, id, egui::TouchPhase::End, pos);
+        }
+    }
+
+    fn motion(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _touch: &WlTouch,
+        _time: u32,
+        id: i32,
+        position: (f64, f64),
+    ) {
+        // motion is always relative to the surface the touch point went down on
+        let Some((surface, pos)) = self.touch_points.get_mut(&id) else {
+            return;
+        };
+        *pos = egui::pos2(position.0 as f32, position.1 as f32);
+        let (surface, pos) = (*surface, *pos);
+        self.push_touch_event(surface, id, egui::TouchPhase::Move, pos);
+    }
+
+    fn shape(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _touch: &WlTouch,
+        _id: i32,
+        _major: f64,
+        _minor: f64,
+    ) {
+    }
+
+    fn orientation(
+        &mut self,
+        _conn: &Connection,
+        _qh: &QueueHandle<Self>,
+        _touch: &WlTouch,
+        _id: i32,
+        _orientation: f64,
+    ) {
+    }
+
+    fn cancel(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _touch: &WlTouch) {
+        let touch_points = std::mem::take(&mut self.touch_points);
+        for (id, (surface, pos)) in touch_points {
+            self.push_touch_event(surface, id, egui::TouchPhase::Cancel, pos);
+        }
+    }
+}
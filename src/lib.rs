@@ -1,13 +1,31 @@
-use application::WgpuLayerShellApp;
-use layer_shell::LayerShellOptions;
+use layer_shell::{LayerShellError, LayerShellOptions, SurfaceId};
 
+pub use application::WgpuLayerShellApp;
+pub use egui_state::{FrameStats, SurfaceSize};
+pub use handle::LayerShellHandle;
+pub use headless::{render_to_image, Headless, Image};
+
+#[cfg(feature = "accesskit")]
+mod accessibility;
 pub(crate) mod application;
+pub(crate) mod clipboard;
 pub(crate) mod egui_state;
+mod fonts;
+mod handle;
+mod headless;
 pub mod layer_shell;
+mod theme;
 
 #[derive(Debug)]
 pub enum Error {
     AppCreation(Box<dyn std::error::Error + Send + Sync>),
+    LayerShell(LayerShellError),
+}
+
+impl From<LayerShellError> for Error {
+    fn from(err: LayerShellError) -> Self {
+        Self::LayerShell(err)
+    }
 }
 
 /// Short for `Result<T, eframe::Error>`.
@@ -15,11 +33,64 @@ pub type Result<T = (), E = Error> = std::result::Result<T, E>;
 
 pub type AppCreator = Box<dyn FnOnce(&egui::Context) -> Result<Box<dyn App>, Error>>;
 
+/// Surface a frame is drawn for, passed to [`App::update_frame`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameContext {
+    pub surface: SurfaceId,
+    /// Size in surface-local (logical) pixels.
+    pub logical_size: (u32, u32),
+    /// Physical pixels per logical pixel.
+    pub scale: f32,
+    /// Name of the output the surface is shown on, e.g. `DP-1`, `None` if it's unknown.
+    pub output_name: Option<String>,
+}
+
 pub trait App {
+    /// Called once for every surface before its first frame, e.g. to install fonts or set the visuals.
+    fn setup(&mut self, _ctx: &egui::Context) {}
+
     fn update(&mut self, ctx: &egui::Context);
 
+    /// Called instead of [`App::update`] when driving multiple surfaces, each has its own context.
+    fn update_surface(&mut self, _surface: SurfaceId, ctx: &egui::Context) {
+        self.update(ctx);
+    }
+
+    /// Called for every frame with the size, scale and output of the surface, e.g. to adapt
+    /// the layout to the screen. Defaults to [`App::update_surface`].
+    fn update_frame(&mut self, frame: &FrameContext, ctx: &egui::Context) {
+        self.update_surface(frame.surface, ctx);
+    }
+
+    /// Called before a frame is drawn with the timestamp of the compositor's frame callback.
+    ///
+    /// The timestamp is in milliseconds with an undefined base, so only differences between
+    /// frames are meaningful, e.g. to compute animation deltas. With several surfaces it is
+    /// called for each of their frames.
+    fn on_frame(&mut self, _time_ms: u32) {}
+
+    /// Parts of the surface that receive pointer and touch input, in points.
+    ///
+    /// Input outside of them passes through to whatever is below the surface, an empty list
+    /// makes the whole surface click-through. `None` accepts input everywhere. Queried after
+    /// every frame, e.g. return the rects of the interactive windows with
+    /// [`egui::Memory::area_rect`].
+    fn input_region(&mut self, _ctx: &egui::Context) -> Option<Vec<egui::Rect>> {
+        None
+    }
+
+    /// Called for every input event before it is passed to egui.
+    ///
+    /// Returning `true` consumes the event, egui never sees it. This allows handling
+    /// shortcuts or gestures egui would otherwise swallow.
+    fn raw_event(&mut self, _event: &egui::Event) -> bool {
+        false
+    }
+
+    /// Called once when the event loop stops, e.g. because the compositor closed the surface.
+    fn on_exit(&mut self) {}
+
     // fn save(&mut self, _storage: &mut dyn Storage) {}
-    // fn on_exit(&mut self) {}
     // fn auto_save_interval(&self) -> std::time::Duration {
     //     std::time::Duration::from_secs(30)
     // }
@@ -28,8 +99,17 @@ pub trait App {
     // }
 }
 
+/// Runs `app` on a layer surface created from `options`.
+///
+/// Blocks the calling thread until the app exits, e.g. through [`LayerShellHandle::exit`] or
+/// because the compositor closed the surface. Use [`WgpuLayerShellApp::step`] to drive the
+/// app from another event loop instead.
+pub fn run(options: LayerShellOptions, app: impl App + 'static) -> Result {
+    run_layer(options, Box::new(|_| Ok(Box::new(app))))
+}
+
 pub fn run_layer(options: LayerShellOptions, app_creator: AppCreator) -> Result {
-    let mut app = WgpuLayerShellApp::new(options, app_creator);
+    let mut app = WgpuLayerShellApp::new(options, app_creator)?;
 
     app.run()
 }
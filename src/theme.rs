@@ -0,0 +1,69 @@
+use crate::LayerShellHandle;
+
+/// Keeps the system theme of the handle in sync with the desktop's color scheme.
+///
+/// The portal is watched until the handle exited, the thread notices it with the next signal.
+pub(crate) fn watch(handle: LayerShellHandle) {
+    handle.set_system_theme(gtk_theme());
+
+    #[cfg(feature = "portal")]
+    std::thread::spawn(move || {
+        if let Err(err) = watch_portal(&handle) {
+            println!("could not read the color scheme from the portal: {err}");
+        }
+    });
+}
+
+/// Guesses the theme from `GTK_THEME`, e.g. `Adwaita:dark`.
+fn gtk_theme() -> Option<egui::Theme> {
+    let gtk_theme = std::env::var("GTK_THEME").ok()?;
+    if gtk_theme.to_lowercase().contains("dark") {
+        Some(egui::Theme::Dark)
+    } else {
+        Some(egui::Theme::Light)
+    }
+}
+
+#[cfg(feature = "portal")]
+fn watch_portal(handle: &LayerShellHandle) -> zbus::Result<()> {
+    use zbus::{blocking::Proxy, zvariant::OwnedValue};
+
+    const NAMESPACE: &str = "org.freedesktop.appearance";
+    const KEY: &str = "color-scheme";
+
+    let connection = zbus::blocking::Connection::session()?;
+    let settings = Proxy::new(
+        &connection,
+        "org.freedesktop.portal.Desktop",
+        "/org/freedesktop/portal/desktop",
+        "org.freedesktop.portal.Settings",
+    )?;
+    // subscribed first, so no change between reading and subscribing is missed
+    let changes = settings.receive_signal("SettingChanged")?;
+
+    let color_scheme: OwnedValue = settings.call("ReadOne", &(NAMESPACE, KEY))?;
+    handle.set_system_theme(portal_theme(color_scheme));
+
+    for message in changes {
+        // the loop is gone, e.g. shut down or replaced by a reconnect
+        if handle.exit_requested() {
+            break;
+        }
+        let (namespace, key, color_scheme): (String, String, OwnedValue) =
+            message.body().deserialize()?;
+        if namespace == NAMESPACE && key == KEY {
+            handle.set_system_theme(portal_theme(color_scheme));
+        }
+    }
+    Ok(())
+}
+
+/// 1 prefers dark, 2 prefers light and 0 has no preference.
+#[cfg(feature = "portal")]
+fn portal_theme(color_scheme: zbus::zvariant::OwnedValue) -> Option<egui::Theme> {
+    match u32::try_from(color_scheme) {
+        Ok(1) => Some(egui::Theme::Dark),
+        Ok(2) => Some(egui::Theme::Light),
+        _ => gtk_theme(),
+    }
+}